@@ -0,0 +1,1056 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+
+pub const MAGIC: &[u8; 4] = b"\x7fELF";
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+const PT_NOTE: u32 = 4;
+
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+const SHT_NULL: u32 = 0;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_DYNAMIC: u32 = 6;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_ALLOC: u64 = 0x2;
+
+const DT_NULL: u64 = 0;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// Smallest page size segments are aligned to.
+const MIN_SEGMENT_ALIGN: u64 = 0x1000;
+
+/// Largest alignment we pad to; real files stop at 2 MiB huge pages, so
+/// anything bigger is a corrupt header.
+const MAX_ALIGN: u64 = 0x20_0000;
+
+/// `e_shstrndx` escape for more sections than fit in the ELF header.
+const SHN_XINDEX: u16 = 0xffff;
+
+/// Word size and byte order, from `e_ident`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub is_64: bool,
+    pub little_endian: bool,
+}
+
+impl Format {
+    fn u16(self, bytes: &[u8], at: usize) -> u16 {
+        let bytes = [bytes[at], bytes[at + 1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(self, bytes: &[u8], at: usize) -> u32 {
+        let bytes = bytes[at..at + 4].try_into().unwrap();
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn u64(self, bytes: &[u8], at: usize) -> u64 {
+        let bytes = bytes[at..at + 8].try_into().unwrap();
+        if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        }
+    }
+
+    fn put_u32(self, bytes: &mut [u8], at: usize, value: u32) {
+        let value = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        bytes[at..at + 4].copy_from_slice(&value);
+    }
+
+    fn put_word(self, bytes: &mut [u8], at: usize, value: u64) -> Result<()> {
+        if self.is_64 {
+            let value = if self.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            bytes[at..at + 8].copy_from_slice(&value);
+        } else {
+            let value = u32::try_from(value).context("value too big for a 32 bit ELF")?;
+            self.put_u32(bytes, at, value);
+        }
+        Ok(())
+    }
+
+    fn header_size(self) -> usize {
+        if self.is_64 { 64 } else { 52 }
+    }
+
+    fn word_size(self) -> u64 {
+        if self.is_64 { 8 } else { 4 }
+    }
+
+    fn program_header_size(self) -> u16 {
+        if self.is_64 { 56 } else { 32 }
+    }
+
+    fn section_header_size(self) -> u16 {
+        if self.is_64 { 64 } else { 40 }
+    }
+}
+
+/// The parts of the ELF header we use, plus its raw bytes so it can be
+/// written back with only the fields we change.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub format: Format,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub phentsize: u16,
+    pub phnum: u16,
+    pub shentsize: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+    raw: Vec<u8>,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 16 || &bytes[..4] != MAGIC {
+            bail!("not an ELF file");
+        }
+
+        let format = Format {
+            is_64: match bytes[4] {
+                ELFCLASS32 => false,
+                ELFCLASS64 => true,
+                other => bail!("unknown ELF class {other}"),
+            },
+            little_endian: match bytes[5] {
+                ELFDATA2LSB => true,
+                ELFDATA2MSB => false,
+                other => bail!("unknown ELF byte order {other}"),
+            },
+        };
+        if bytes.len() < format.header_size() {
+            bail!("truncated ELF header");
+        }
+        let raw = bytes[..format.header_size()].to_vec();
+
+        let header = if format.is_64 {
+            Self {
+                format,
+                phoff: format.u64(&raw, 32),
+                shoff: format.u64(&raw, 40),
+                phentsize: format.u16(&raw, 54),
+                phnum: format.u16(&raw, 56),
+                shentsize: format.u16(&raw, 58),
+                shnum: format.u16(&raw, 60),
+                shstrndx: format.u16(&raw, 62),
+                raw,
+            }
+        } else {
+            Self {
+                format,
+                phoff: u64::from(format.u32(&raw, 28)),
+                shoff: u64::from(format.u32(&raw, 32)),
+                phentsize: format.u16(&raw, 42),
+                phnum: format.u16(&raw, 44),
+                shentsize: format.u16(&raw, 46),
+                shnum: format.u16(&raw, 48),
+                shstrndx: format.u16(&raw, 50),
+                raw,
+            }
+        };
+
+        // the tables are parsed with our own layout, so other sizes would
+        // read past (or short of) each entry
+        if header.phnum != 0 && header.phentsize != format.program_header_size() {
+            bail!("unexpected program header size {}", header.phentsize);
+        }
+        if header.shnum != 0 && header.shentsize != format.section_header_size() {
+            bail!("unexpected section header size {}", header.shentsize);
+        }
+
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let format = self.format;
+        let mut raw = self.raw.clone();
+        if format.is_64 {
+            format.put_word(&mut raw, 32, self.phoff)?;
+            format.put_word(&mut raw, 40, self.shoff)?;
+        } else {
+            format.put_word(&mut raw, 28, self.phoff)?;
+            format.put_word(&mut raw, 32, self.shoff)?;
+        }
+        Ok(raw)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub paddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    fn parse(format: Format, bytes: &[u8]) -> Self {
+        if format.is_64 {
+            Self {
+                p_type: format.u32(bytes, 0),
+                flags: format.u32(bytes, 4),
+                offset: format.u64(bytes, 8),
+                vaddr: format.u64(bytes, 16),
+                paddr: format.u64(bytes, 24),
+                filesz: format.u64(bytes, 32),
+                memsz: format.u64(bytes, 40),
+                align: format.u64(bytes, 48),
+            }
+        } else {
+            Self {
+                p_type: format.u32(bytes, 0),
+                offset: u64::from(format.u32(bytes, 4)),
+                vaddr: u64::from(format.u32(bytes, 8)),
+                paddr: u64::from(format.u32(bytes, 12)),
+                filesz: u64::from(format.u32(bytes, 16)),
+                memsz: u64::from(format.u32(bytes, 20)),
+                flags: format.u32(bytes, 24),
+                align: u64::from(format.u32(bytes, 28)),
+            }
+        }
+    }
+
+    fn write(&self, format: Format, bytes: &mut [u8]) -> Result<()> {
+        format.put_u32(bytes, 0, self.p_type);
+        if format.is_64 {
+            format.put_u32(bytes, 4, self.flags);
+            format.put_word(bytes, 8, self.offset)?;
+            format.put_word(bytes, 16, self.vaddr)?;
+            format.put_word(bytes, 24, self.paddr)?;
+            format.put_word(bytes, 32, self.filesz)?;
+            format.put_word(bytes, 40, self.memsz)?;
+            format.put_word(bytes, 48, self.align)?;
+        } else {
+            format.put_word(bytes, 4, self.offset)?;
+            format.put_word(bytes, 8, self.vaddr)?;
+            format.put_word(bytes, 12, self.paddr)?;
+            format.put_word(bytes, 16, self.filesz)?;
+            format.put_word(bytes, 20, self.memsz)?;
+            format.put_u32(bytes, 24, self.flags);
+            format.put_word(bytes, 28, self.align)?;
+        }
+        Ok(())
+    }
+
+    /// Point the segment at `len` bytes at `offset`, mapped at `vaddr`.
+    fn relocate(&mut self, offset: u64, vaddr: u64, len: u64) {
+        self.offset = offset;
+        self.vaddr = vaddr;
+        self.paddr = vaddr;
+        self.filesz = len;
+        self.memsz = len;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub sh_type: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl SectionHeader {
+    fn parse(format: Format, bytes: &[u8]) -> Self {
+        if format.is_64 {
+            Self {
+                name: format.u32(bytes, 0),
+                sh_type: format.u32(bytes, 4),
+                flags: format.u64(bytes, 8),
+                addr: format.u64(bytes, 16),
+                offset: format.u64(bytes, 24),
+                size: format.u64(bytes, 32),
+                link: format.u32(bytes, 40),
+                info: format.u32(bytes, 44),
+                addralign: format.u64(bytes, 48),
+                entsize: format.u64(bytes, 56),
+            }
+        } else {
+            Self {
+                name: format.u32(bytes, 0),
+                sh_type: format.u32(bytes, 4),
+                flags: u64::from(format.u32(bytes, 8)),
+                addr: u64::from(format.u32(bytes, 12)),
+                offset: u64::from(format.u32(bytes, 16)),
+                size: u64::from(format.u32(bytes, 20)),
+                link: format.u32(bytes, 24),
+                info: format.u32(bytes, 28),
+                addralign: u64::from(format.u32(bytes, 32)),
+                entsize: u64::from(format.u32(bytes, 36)),
+            }
+        }
+    }
+
+    fn write(&self, format: Format, bytes: &mut [u8]) -> Result<()> {
+        let w = format.word_size() as usize;
+        format.put_u32(bytes, 0, self.name);
+        format.put_u32(bytes, 4, self.sh_type);
+        format.put_word(bytes, 8, self.flags)?;
+        format.put_word(bytes, 8 + w, self.addr)?;
+        format.put_word(bytes, 8 + 2 * w, self.offset)?;
+        format.put_word(bytes, 8 + 3 * w, self.size)?;
+        format.put_u32(bytes, 8 + 4 * w, self.link);
+        format.put_u32(bytes, 12 + 4 * w, self.info);
+        format.put_word(bytes, 16 + 4 * w, self.addralign)?;
+        format.put_word(bytes, 16 + 5 * w, self.entsize)?;
+        Ok(())
+    }
+
+    fn has_data(&self) -> bool {
+        self.sh_type != SHT_NULL && self.sh_type != SHT_NOBITS
+    }
+
+    fn end(&self) -> u64 {
+        if self.has_data() {
+            self.offset.saturating_add(self.size)
+        } else {
+            0
+        }
+    }
+}
+
+/// Headers of an ELF file, read without loading the (possibly huge) rest.
+#[derive(Debug, Clone)]
+pub struct Elf {
+    pub header: Header,
+    pub program_headers: Vec<ProgramHeader>,
+    pub sections: Vec<SectionHeader>,
+    /// Contents of the section name string table.
+    section_names: Vec<u8>,
+}
+
+impl Elf {
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut ident = vec![0; 64];
+        reader.seek(SeekFrom::Start(0))?;
+        let n = read_up_to(reader, &mut ident)?;
+        let header = Header::parse(&ident[..n])?;
+        let format = header.format;
+
+        let program_headers = read_table(reader, header.phoff, header.phentsize, header.phnum)?
+            .iter()
+            .map(|bytes| ProgramHeader::parse(format, bytes))
+            .collect();
+
+        if header.shnum == 0 && header.shoff != 0 {
+            bail!("ELF files with extended section numbering aren't supported");
+        }
+        if header.shstrndx == SHN_XINDEX {
+            bail!("ELF files with extended section name indices aren't supported");
+        }
+        let sections: Vec<_> = read_table(reader, header.shoff, header.shentsize, header.shnum)?
+            .iter()
+            .map(|bytes| SectionHeader::parse(format, bytes))
+            .collect();
+
+        let section_names = match sections.get(usize::from(header.shstrndx)) {
+            Some(names) if names.has_data() => {
+                read_at(reader, names.offset, names.size).context("truncated section names")?
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            header,
+            program_headers,
+            sections,
+            section_names,
+        })
+    }
+
+    pub fn section_name(&self, section: &SectionHeader) -> &str {
+        let start = section.name as usize;
+        let Some(rest) = self.section_names.get(start..) else {
+            return "";
+        };
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        std::str::from_utf8(&rest[..end]).unwrap_or("")
+    }
+
+    fn segment(&self, p_type: u32) -> Option<&ProgramHeader> {
+        self.program_headers
+            .iter()
+            .find(|phdr| phdr.p_type == p_type)
+    }
+
+    fn loads(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers
+            .iter()
+            .filter(|phdr| phdr.p_type == PT_LOAD)
+    }
+
+    /// File offset of the byte mapped at `vaddr`.
+    fn offset_of(&self, vaddr: u64) -> Option<u64> {
+        self.loads()
+            .find(|phdr| phdr.vaddr <= vaddr && vaddr - phdr.vaddr < phdr.filesz)
+            .map(|phdr| phdr.offset.saturating_add(vaddr - phdr.vaddr))
+    }
+
+    /// The `PT_INTERP` path, `None` for libraries.
+    fn interpreter<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<String>> {
+        let Some(phdr) = self.segment(PT_INTERP) else {
+            return Ok(None);
+        };
+        let bytes = read_at(reader, phdr.offset, phdr.filesz)?;
+        let interpreter =
+            String::from_utf8(c_str(&bytes).to_vec()).context("non UTF-8 PT_INTERP")?;
+        Ok(Some(interpreter))
+    }
+
+    /// The `PT_DYNAMIC` entries up to `DT_NULL` and the dynamic string table
+    /// they refer to, `None` for static executables.
+    fn dynamic<R: Read + Seek>(&self, reader: &mut R) -> Result<Option<Dynamic>> {
+        let Some(phdr) = self.segment(PT_DYNAMIC) else {
+            return Ok(None);
+        };
+        let format = self.header.format;
+        let entry_size = 2 * format.word_size() as usize;
+        let bytes = read_at(reader, phdr.offset, phdr.filesz)?;
+        let entries: Vec<_> = bytes
+            .chunks_exact(entry_size)
+            .map(|bytes| DynamicEntry::parse(format, bytes))
+            .take_while(|entry| entry.tag != DT_NULL)
+            .collect();
+
+        let mut dynamic = Dynamic {
+            entries,
+            strtab_addr: 0,
+            strtab: Vec::new(),
+        };
+        dynamic.strtab_addr = dynamic.value(DT_STRTAB).context("no DT_STRTAB")?;
+        let strtab_size = dynamic.value(DT_STRSZ).context("no DT_STRSZ")?;
+        let strtab_offset = self
+            .offset_of(dynamic.strtab_addr)
+            .context("DT_STRTAB isn't mapped from the file")?;
+        dynamic.strtab = read_at(reader, strtab_offset, strtab_size)?;
+
+        Ok(Some(dynamic))
+    }
+
+    /// Indices of the sections `strip` would drop: the symbol table, its
+    /// string table and debug info, none of which the dynamic linker reads.
+    fn strippable_sections(&self) -> Vec<usize> {
+        let mut strip = vec![false; self.sections.len()];
+
+        for (index, section) in self.sections.iter().enumerate() {
+            if section.flags & SHF_ALLOC != 0 || !section.has_data() {
+                continue;
+            }
+            let name = self.section_name(section);
+            if section.sh_type == SHT_SYMTAB
+                || name.starts_with(".debug")
+                || name.starts_with(".zdebug")
+            {
+                strip[index] = true;
+            }
+        }
+
+        // the symbol table's strings go too, unless something we keep uses them
+        for section in &self.sections {
+            if section.sh_type != SHT_SYMTAB {
+                continue;
+            }
+            let link = section.link as usize;
+            let used = link == usize::from(self.header.shstrndx)
+                || self
+                    .sections
+                    .iter()
+                    .enumerate()
+                    .any(|(index, other)| !strip[index] && other.link as usize == link);
+            if let Some(linked) = self.sections.get(link)
+                && !used
+                && linked.flags & SHF_ALLOC == 0
+            {
+                strip[link] = true;
+            }
+        }
+
+        strip
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &strip)| strip.then_some(index))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DynamicEntry {
+    tag: u64,
+    value: u64,
+}
+
+impl DynamicEntry {
+    fn parse(format: Format, bytes: &[u8]) -> Self {
+        if format.is_64 {
+            Self {
+                tag: format.u64(bytes, 0),
+                value: format.u64(bytes, 8),
+            }
+        } else {
+            Self {
+                tag: u64::from(format.u32(bytes, 0)),
+                value: u64::from(format.u32(bytes, 4)),
+            }
+        }
+    }
+
+    fn write(&self, format: Format, bytes: &mut [u8]) -> Result<()> {
+        format.put_word(bytes, 0, self.tag)?;
+        format.put_word(bytes, format.word_size() as usize, self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Dynamic {
+    entries: Vec<DynamicEntry>,
+    strtab_addr: u64,
+    strtab: Vec<u8>,
+}
+
+impl Dynamic {
+    fn string(&self, offset: u64) -> Result<&str> {
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.strtab.get(offset..))
+            .context("string outside the dynamic string table")?;
+        std::str::from_utf8(c_str(bytes)).context("non UTF-8 dynamic string")
+    }
+
+    fn value(&self, tag: u64) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.tag == tag)
+            .map(|entry| entry.value)
+    }
+
+    /// What the dynamic linker searches: `DT_RUNPATH`, or the older
+    /// `DT_RPATH` if there isn't one.
+    fn runpath(&self) -> Result<Option<&str>> {
+        self.value(DT_RUNPATH)
+            .or_else(|| self.value(DT_RPATH))
+            .map(|offset| self.string(offset))
+            .transpose()
+    }
+}
+
+/// Up to the first NUL.
+fn c_str(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+/// `len` comes from the file, so it's checked against the file's size before
+/// anything is allocated.
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if offset.checked_add(len).is_none_or(|end| end > file_len) {
+        bail!("truncated ELF file");
+    }
+    let mut data = vec![0; usize::try_from(len)?];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut data).context("truncated ELF file")?;
+    Ok(data)
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn read_table<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    entry_size: u16,
+    count: u16,
+) -> Result<Vec<Vec<u8>>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut data = vec![0; usize::from(entry_size) * usize::from(count)];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader
+        .read_exact(&mut data)
+        .context("truncated ELF headers")?;
+    Ok(data
+        .chunks_exact(usize::from(entry_size))
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+fn copy_range<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    offset: u64,
+    len: u64,
+) -> Result<()> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let copied = io::copy(&mut reader.take(len), writer)?;
+    if copied != len {
+        bail!("truncated ELF file");
+    }
+    Ok(())
+}
+
+fn pad_to<W: Write>(writer: &mut W, pos: &mut u64, align: u64) -> Result<()> {
+    if align > MAX_ALIGN {
+        bail!("unexpected alignment {align:#x}");
+    }
+    let aligned = pos.next_multiple_of(align.max(1));
+    writer.write_all(&vec![0; (aligned - *pos) as usize])?;
+    *pos = aligned;
+    Ok(())
+}
+
+/// Remove the symbol table and debug sections from the ELF file at `path`,
+/// in place, like `strip` does for a shared library. Returns how many bytes
+/// were saved (0 if there was nothing to strip).
+///
+/// Everything the dynamic linker maps is copied byte for byte; dropped
+/// sections keep their slot in the section header table as `SHT_NULL`, so no
+/// section index (e.g. in `.dynsym`) changes.
+pub fn strip(path: &Path) -> Result<u64> {
+    let mut input = File::open(path).with_context(|| format!("open {path:?}"))?;
+    let input_len = input.metadata()?.len();
+    let elf = Elf::read(&mut input)?;
+    let format = elf.header.format;
+
+    let stripped = elf.strippable_sections();
+    if stripped.is_empty() {
+        return Ok(0);
+    }
+
+    // everything up to the end of the last mapped byte stays where it is
+    let phdrs_end = elf
+        .header
+        .phoff
+        .saturating_add(u64::from(elf.header.phentsize) * u64::from(elf.header.phnum));
+    let keep_end = elf
+        .program_headers
+        .iter()
+        .map(|phdr| phdr.offset.saturating_add(phdr.filesz))
+        .chain(
+            elf.sections
+                .iter()
+                .filter(|section| section.flags & SHF_ALLOC != 0)
+                .map(SectionHeader::end),
+        )
+        .chain([format.header_size() as u64, phdrs_end])
+        .max()
+        .unwrap_or_default();
+    if keep_end > input_len {
+        bail!("ELF headers point past the end of the file");
+    }
+
+    let mut sections = elf.sections.clone();
+    for &index in &stripped {
+        sections[index] = SectionHeader {
+            name: sections[index].name,
+            sh_type: SHT_NULL,
+            flags: 0,
+            addr: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            addralign: 0,
+            entsize: 0,
+        };
+    }
+
+    let output_len = replace(path, ".strip", input, |input, output| {
+        let mut output = io::BufWriter::new(output);
+        copy_range(input, &mut output, 0, keep_end)?;
+        let mut pos = keep_end;
+
+        // unmapped sections we keep (.comment, .shstrtab, ...) past that
+        // point are packed after it
+        let mut moved: Vec<_> = (0..sections.len())
+            .filter(|&index| {
+                let section = &sections[index];
+                section.has_data() && section.flags & SHF_ALLOC == 0 && section.end() > keep_end
+            })
+            .collect();
+        moved.sort_by_key(|&index| sections[index].offset);
+        for index in moved {
+            let section = &mut sections[index];
+            pad_to(&mut output, &mut pos, section.addralign)?;
+            copy_range(input, &mut output, section.offset, section.size)?;
+            section.offset = pos;
+            pos += section.size;
+        }
+
+        pad_to(&mut output, &mut pos, format.word_size())?;
+        let mut header = elf.header.clone();
+        header.shoff = pos;
+
+        output.write_all(&section_table(&elf.header, &sections)?)?;
+        pos += u64::from(format.section_header_size()) * sections.len() as u64;
+
+        let output = output
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        output.seek(SeekFrom::Start(0))?;
+        output.write_all(&header.to_bytes()?)?;
+
+        Ok(pos)
+    })?;
+
+    Ok(input_len.saturating_sub(output_len))
+}
+
+fn section_table(header: &Header, sections: &[SectionHeader]) -> Result<Vec<u8>> {
+    let entry_size = usize::from(header.format.section_header_size());
+    let mut table = vec![0; entry_size * sections.len()];
+    for (section, bytes) in sections.iter().zip(table.chunks_exact_mut(entry_size)) {
+        section.write(header.format, bytes)?;
+    }
+    Ok(table)
+}
+
+fn program_table(header: &Header, phdrs: &[ProgramHeader]) -> Result<Vec<u8>> {
+    let entry_size = usize::from(header.format.program_header_size());
+    let mut table = vec![0; entry_size * phdrs.len()];
+    for (phdr, bytes) in phdrs.iter().zip(table.chunks_exact_mut(entry_size)) {
+        phdr.write(header.format, bytes)?;
+    }
+    Ok(table)
+}
+
+/// Write a replacement for `path` next to it with `write` (given the
+/// original and the new file), then swap it in with the original's
+/// permissions. The original is closed first, so this works on Windows too.
+fn replace<T>(
+    path: &Path,
+    suffix: &str,
+    mut input: File,
+    write: impl FnOnce(&mut File, &mut File) -> Result<T>,
+) -> Result<T> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    let temp_path = path.with_file_name(file_name);
+
+    let result = File::create(&temp_path)
+        .with_context(|| format!("create {temp_path:?}"))
+        .and_then(|mut output| {
+            let value = write(&mut input, &mut output)?;
+            output.sync_all()?;
+            Ok(value)
+        });
+    let value = match result {
+        Ok(value) => value,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+
+    let permissions = input.metadata()?.permissions();
+    drop(input);
+    fs::set_permissions(&temp_path, permissions)
+        .with_context(|| format!("set_permissions {temp_path:?}"))?;
+    fs::rename(&temp_path, path).with_context(|| format!("rename {temp_path:?} -> {path:?}"))?;
+
+    Ok(value)
+}
+
+/// What `patch` changes; `None` leaves it alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Patch<'a> {
+    /// Dynamic linker for executables; libraries don't have one and are left
+    /// as they are.
+    pub interpreter: Option<&'a str>,
+    /// `:` separated directories to search for libraries before any the file
+    /// already names. Always written as `DT_RUNPATH`, so `LD_LIBRARY_PATH`
+    /// still wins.
+    pub runpath: Option<&'a str>,
+}
+
+/// `prefix`, then the directories of `existing` not already in it.
+fn merge_runpath(prefix: &str, existing: Option<&str>) -> String {
+    let mut dirs = Vec::new();
+    for dir in prefix
+        .split(':')
+        .chain(existing.into_iter().flat_map(|e| e.split(':')))
+    {
+        if !dir.is_empty() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs.join(":")
+}
+
+/// Set the interpreter and library search path of the ELF file at `path`,
+/// in place, like `patchelf --set-interpreter --set-rpath` does for binaries
+/// built for another distribution's file layout. Returns `false` if they
+/// were already right.
+///
+/// The new strings and a rebuilt `.dynamic` go in a segment appended to the
+/// file. It takes the program header slot of a `PT_NOTE`, which the loader
+/// doesn't need, so nothing already in the file has to move; a file can
+/// only be patched this way once per note.
+pub fn patch(path: &Path, patch: Patch<'_>) -> Result<bool> {
+    let mut input = File::open(path).with_context(|| format!("open {path:?}"))?;
+    let input_len = input.metadata()?.len();
+    let elf = Elf::read(&mut input)?;
+    let format = elf.header.format;
+
+    let interpreter = match patch.interpreter {
+        Some(wanted) => elf
+            .interpreter(&mut input)?
+            .filter(|current| current != wanted)
+            .map(|_| wanted),
+        None => None,
+    };
+
+    let mut dynamic = None;
+    if let Some(prefix) = patch.runpath
+        && let Some(current) = elf.dynamic(&mut input)?
+    {
+        let runpath = merge_runpath(prefix, current.runpath()?);
+        let up_to_date = current.value(DT_RPATH).is_none()
+            && current
+                .value(DT_RUNPATH)
+                .map(|offset| current.string(offset))
+                .transpose()?
+                == Some(runpath.as_str());
+        if !up_to_date {
+            dynamic = Some((current, runpath));
+        }
+    }
+
+    if interpreter.is_none() && dynamic.is_none() {
+        return Ok(false);
+    }
+
+    let mut phdrs = elf.program_headers.clone();
+    let note = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_NOTE)
+        .context("no PT_NOTE to make room for a new segment (already patched?)")?;
+    phdrs.remove(note);
+
+    let align = elf
+        .loads()
+        .map(|phdr| phdr.align)
+        .max()
+        .unwrap_or_default()
+        .max(MIN_SEGMENT_ALIGN);
+    if align > MAX_ALIGN {
+        bail!("unexpected segment alignment {align:#x}");
+    }
+    let segment_offset = input_len.next_multiple_of(align);
+    let segment_vaddr = elf
+        .loads()
+        .map(|phdr| phdr.vaddr.saturating_add(phdr.memsz))
+        .max()
+        .context("no PT_LOAD segments")?
+        .checked_next_multiple_of(align)
+        .context("PT_LOAD segment past the end of the address space")?;
+
+    let mut segment = Vec::new();
+    let mut sections = elf.sections.clone();
+    // where `bytes` end up once appended to the new segment
+    let append = |segment: &mut Vec<u8>, bytes: &[u8]| {
+        let at = segment.len() as u64;
+        segment.extend_from_slice(bytes);
+        Moved {
+            offset: segment_offset + at,
+            vaddr: segment_vaddr + at,
+            len: bytes.len() as u64,
+        }
+    };
+
+    if let Some(interpreter) = interpreter {
+        let mut bytes = interpreter.as_bytes().to_vec();
+        bytes.push(0);
+        let moved = append(&mut segment, &bytes);
+        moved.apply(&mut phdrs, PT_INTERP, &mut sections, SHT_PROGBITS);
+    }
+
+    if let Some((current, runpath)) = dynamic {
+        // the old strings stay where they were in the copy, so every offset
+        // into them (DT_NEEDED, DT_SONAME, symbol names, ...) still works
+        let mut strtab = current.strtab.clone();
+        let runpath_offset = strtab.len() as u64;
+        strtab.extend_from_slice(runpath.as_bytes());
+        strtab.push(0);
+        let moved_strtab = append(&mut segment, &strtab);
+        if let Some(section) = sections.iter_mut().find(|section| {
+            section.sh_type == SHT_STRTAB
+                && section.flags & SHF_ALLOC != 0
+                && section.addr == current.strtab_addr
+        }) {
+            moved_strtab.apply_to_section(section);
+        }
+
+        let mut entries: Vec<_> = current
+            .entries
+            .iter()
+            .filter(|entry| entry.tag != DT_RPATH && entry.tag != DT_RUNPATH)
+            .map(|&entry| match entry.tag {
+                DT_STRTAB => DynamicEntry {
+                    tag: DT_STRTAB,
+                    value: moved_strtab.vaddr,
+                },
+                DT_STRSZ => DynamicEntry {
+                    tag: DT_STRSZ,
+                    value: moved_strtab.len,
+                },
+                _ => entry,
+            })
+            .collect();
+        entries.push(DynamicEntry {
+            tag: DT_RUNPATH,
+            value: runpath_offset,
+        });
+        entries.push(DynamicEntry {
+            tag: DT_NULL,
+            value: 0,
+        });
+
+        let entry_size = 2 * format.word_size() as usize;
+        let mut table = vec![0; entry_size * entries.len()];
+        for (entry, bytes) in entries.iter().zip(table.chunks_exact_mut(entry_size)) {
+            entry.write(format, bytes)?;
+        }
+        segment.resize(segment.len().next_multiple_of(entry_size), 0);
+        let moved = append(&mut segment, &table);
+        moved.apply(&mut phdrs, PT_DYNAMIC, &mut sections, SHT_DYNAMIC);
+    }
+
+    // loaders size the whole mapping from the first and last PT_LOAD, so the
+    // new one, highest in memory, goes after the others
+    let last_load = phdrs
+        .iter()
+        .rposition(|phdr| phdr.p_type == PT_LOAD)
+        .map_or(0, |index| index + 1);
+    phdrs.insert(
+        last_load,
+        ProgramHeader {
+            p_type: PT_LOAD,
+            // the dynamic linker writes to .dynamic
+            flags: PF_R | PF_W,
+            offset: segment_offset,
+            vaddr: segment_vaddr,
+            paddr: segment_vaddr,
+            filesz: segment.len() as u64,
+            memsz: segment.len() as u64,
+            align,
+        },
+    );
+
+    replace(path, ".patch", input, |input, output| {
+        let mut writer = io::BufWriter::new(&mut *output);
+        copy_range(input, &mut writer, 0, input_len)?;
+        let mut pos = input_len;
+        pad_to(&mut writer, &mut pos, align)?;
+        writer.write_all(&segment)?;
+        writer.flush()?;
+        drop(writer);
+
+        output.seek(SeekFrom::Start(elf.header.phoff))?;
+        output.write_all(&program_table(&elf.header, &phdrs)?)?;
+        if !sections.is_empty() {
+            output.seek(SeekFrom::Start(elf.header.shoff))?;
+            output.write_all(&section_table(&elf.header, &sections)?)?;
+        }
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Where `patch` put a copy of some part of the file.
+#[derive(Debug, Clone, Copy)]
+struct Moved {
+    offset: u64,
+    vaddr: u64,
+    len: u64,
+}
+
+impl Moved {
+    /// Point the `p_type` segment and the section it held at the copy. The
+    /// section headers are only for tools, the loader goes by the segment.
+    fn apply(
+        self,
+        phdrs: &mut [ProgramHeader],
+        p_type: u32,
+        sections: &mut [SectionHeader],
+        sh_type: u32,
+    ) {
+        let Some(phdr) = phdrs.iter_mut().find(|phdr| phdr.p_type == p_type) else {
+            return;
+        };
+        if let Some(section) = sections.iter_mut().find(|section| {
+            section.sh_type == sh_type
+                && section.flags & SHF_ALLOC != 0
+                && section.addr == phdr.vaddr
+        }) {
+            self.apply_to_section(section);
+        }
+        phdr.relocate(self.offset, self.vaddr, self.len);
+    }
+
+    fn apply_to_section(self, section: &mut SectionHeader) {
+        section.offset = self.offset;
+        section.addr = self.vaddr;
+        section.size = self.len;
+    }
+}
@@ -0,0 +1,390 @@
+use std::{env, process::Command};
+
+use super::*;
+use crate::test_util::scratch_dir;
+
+struct Writer {
+    format: Format,
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend(if self.format.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        });
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(if self.format.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        });
+    }
+
+    fn word(&mut self, value: u64) {
+        if self.format.is_64 {
+            self.bytes.extend(if self.format.little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            });
+        } else {
+            self.u32(value as u32);
+        }
+    }
+}
+
+const TEXT: &[u8] = &[0xaa; 16];
+const COMMENT: &[u8] = b"GCC: (GNU) 14\0";
+const SECTION_NAMES: &[u8] = b"\0.text\0.symtab\0.strtab\0.debug_info\0.comment\0.shstrtab\0";
+
+/// A minimal shared library: one `PT_LOAD` covering `.text`, followed by
+/// the unmapped sections `strip` deals with.
+fn build(format: Format) -> Vec<u8> {
+    let ehsize = format.header_size() as u64;
+    let phentsize: u64 = if format.is_64 { 56 } else { 32 };
+    let shentsize: u16 = if format.is_64 { 64 } else { 40 };
+
+    let text_offset = ehsize + phentsize;
+    let mut data = Vec::new();
+    let mut place = |contents: &[u8]| {
+        let offset = text_offset + data.len() as u64;
+        data.extend_from_slice(contents);
+        (offset, contents.len() as u64)
+    };
+    let text = place(TEXT);
+    let symtab = place(&[0x55; 48]);
+    let strtab = place(b"\0foo\0");
+    let debug_info = place(&[0xdd; 100]);
+    let comment = place(COMMENT);
+    let names = place(SECTION_NAMES);
+    let shoff = (text_offset + data.len() as u64).next_multiple_of(8);
+
+    let name = |s: &str| {
+        SECTION_NAMES
+            .windows(s.len() + 1)
+            .position(|w| &w[..s.len()] == s.as_bytes() && w[s.len()] == 0)
+            .unwrap() as u32
+    };
+    let section = |n: &str, sh_type, flags, (offset, size): (u64, u64), link| SectionHeader {
+        name: name(n),
+        sh_type,
+        flags,
+        addr: if flags & SHF_ALLOC != 0 { offset } else { 0 },
+        offset,
+        size,
+        link,
+        info: 0,
+        addralign: 1,
+        entsize: 0,
+    };
+    let sections = [
+        section("", SHT_NULL, 0, (0, 0), 0),
+        section(".text", SHT_PROGBITS, SHF_ALLOC | 0x4, text, 0),
+        section(".symtab", SHT_SYMTAB, 0, symtab, 3),
+        section(".strtab", SHT_STRTAB, 0, strtab, 0),
+        section(".debug_info", SHT_PROGBITS, 0, debug_info, 0),
+        section(".comment", SHT_PROGBITS, 0x30, comment, 0),
+        section(".shstrtab", SHT_STRTAB, 0, names, 0),
+    ];
+
+    let mut w = Writer {
+        format,
+        bytes: Vec::new(),
+    };
+    w.bytes.extend_from_slice(MAGIC);
+    w.bytes
+        .push(if format.is_64 { ELFCLASS64 } else { ELFCLASS32 });
+    w.bytes.push(if format.little_endian {
+        ELFDATA2LSB
+    } else {
+        ELFDATA2MSB
+    });
+    w.bytes.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    w.u16(3); // ET_DYN
+    w.u16(62);
+    w.u32(1);
+    w.word(0); // entry
+    w.word(ehsize); // phoff
+    w.word(shoff);
+    w.u32(0);
+    w.u16(ehsize as u16);
+    w.u16(phentsize as u16);
+    w.u16(1);
+    w.u16(shentsize);
+    w.u16(sections.len() as u16);
+    w.u16(6);
+    assert_eq!(w.bytes.len() as u64, ehsize);
+
+    let load_size = text.0 + text.1;
+    if format.is_64 {
+        w.u32(PT_LOAD);
+        w.u32(5);
+        w.word(0);
+        w.word(0);
+        w.word(0);
+        w.word(load_size);
+        w.word(load_size);
+        w.word(0x1000);
+    } else {
+        w.u32(PT_LOAD);
+        w.word(0);
+        w.word(0);
+        w.word(0);
+        w.word(load_size);
+        w.word(load_size);
+        w.u32(5);
+        w.word(0x1000);
+    }
+
+    w.bytes.extend(data);
+    w.bytes.resize(shoff as usize, 0);
+    for section in sections {
+        let start = w.bytes.len();
+        w.bytes.resize(start + usize::from(shentsize), 0);
+        section.write(format, &mut w.bytes[start..]).unwrap();
+    }
+    w.bytes
+}
+
+fn strip_synthetic(name: &str, format: Format) {
+    let dir = scratch_dir(name);
+    let path = dir.join("libtest.so");
+    let original = build(format);
+    fs::write(&path, &original).unwrap();
+
+    let saved = strip(&path).unwrap();
+    let stripped = fs::read(&path).unwrap();
+    assert_eq!(saved, (original.len() - stripped.len()) as u64);
+    assert!(saved > 0);
+
+    let elf = Elf::read(&mut File::open(&path).unwrap()).unwrap();
+    assert_eq!(elf.header.format, format);
+    assert_eq!(elf.sections.len(), 7);
+
+    let types: Vec<_> = elf.sections.iter().map(|s| s.sh_type).collect();
+    assert_eq!(
+        types,
+        [
+            SHT_NULL,
+            SHT_PROGBITS,
+            SHT_NULL,
+            SHT_NULL,
+            SHT_NULL,
+            SHT_PROGBITS,
+            SHT_STRTAB
+        ]
+    );
+
+    // mapped bytes are untouched, only the header's e_shoff changes
+    let text = elf.sections[1];
+    let text_range = text.offset as usize..(text.offset + text.size) as usize;
+    assert_eq!(&stripped[text_range.clone()], TEXT);
+    let ehsize = format.header_size();
+    assert_eq!(
+        &original[ehsize..text_range.end],
+        &stripped[ehsize..text_range.end]
+    );
+
+    // kept unmapped sections moved but intact
+    let comment = elf.sections[5];
+    assert_eq!(elf.section_name(&comment), ".comment");
+    assert_eq!(
+        &stripped[comment.offset as usize..(comment.offset + comment.size) as usize],
+        COMMENT
+    );
+    assert_eq!(elf.section_name(&elf.sections[6]), ".shstrtab");
+
+    // nothing left to do the second time
+    assert_eq!(strip(&path).unwrap(), 0);
+    assert!(!dir.join("libtest.so.strip").exists());
+}
+
+#[test]
+fn strips_64_bit_little_endian() {
+    strip_synthetic(
+        "64le",
+        Format {
+            is_64: true,
+            little_endian: true,
+        },
+    );
+}
+
+#[test]
+fn strips_32_bit_big_endian() {
+    strip_synthetic(
+        "32be",
+        Format {
+            is_64: false,
+            little_endian: false,
+        },
+    );
+}
+
+#[test]
+fn rejects_non_elf() {
+    let dir = scratch_dir("not-elf");
+    let path = dir.join("libcef.so");
+    fs::write(&path, b"MZ this is a PE file").unwrap();
+
+    assert!(format!("{:#}", strip(&path).unwrap_err()).contains("not an ELF file"));
+    assert_eq!(fs::read(&path).unwrap(), b"MZ this is a PE file");
+}
+
+/// Header values come from a downloaded file, so bad ones must be errors
+/// rather than panics or huge allocations.
+#[test]
+fn rejects_malformed_headers() {
+    let original = build(Format {
+        is_64: true,
+        little_endian: true,
+    });
+    let shoff = u64::from_le_bytes(original[40..48].try_into().unwrap()) as usize;
+    let section = |index: usize, field: usize| shoff + index * 64 + field;
+
+    for (name, at, value, expected) in [
+        (
+            "phentsize-zero",
+            54,
+            &0u16.to_le_bytes()[..],
+            "program header size",
+        ),
+        (
+            "shentsize-short",
+            58,
+            &16u16.to_le_bytes()[..],
+            "section header size",
+        ),
+        // .comment is moved, so its alignment is padded to
+        (
+            "huge-align",
+            section(5, 48),
+            &(1u64 << 40).to_le_bytes()[..],
+            "alignment",
+        ),
+        (
+            "huge-names",
+            section(6, 32),
+            &u64::MAX.to_le_bytes()[..],
+            "truncated",
+        ),
+        (
+            "huge-filesz",
+            64 + 32,
+            &(u64::MAX / 2).to_le_bytes()[..],
+            "past the end",
+        ),
+    ] {
+        let dir = scratch_dir(name);
+        let path = dir.join("libtest.so");
+        let mut bytes = original.clone();
+        bytes[at..at + value.len()].copy_from_slice(value);
+        fs::write(&path, &bytes).unwrap();
+
+        let error = format!("{:#}", strip(&path).unwrap_err());
+        assert!(error.contains(expected), "{name}: {error}");
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+}
+
+/// The test binary has a symbol table and debug info; a stripped copy must
+/// still run.
+#[test]
+fn stripped_binary_still_runs() {
+    let dir = scratch_dir("exe");
+    let path = dir.join("tests");
+    fs::copy(env::current_exe().unwrap(), &path).unwrap();
+
+    assert!(strip(&path).unwrap() > 0);
+
+    let output = Command::new(&path)
+        .args(["--list", "elf::tests::rejects_non_elf"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("rejects_non_elf"));
+}
+
+#[test]
+fn merges_runpath() {
+    assert_eq!(merge_runpath("/nix/a:/nix/b", None), "/nix/a:/nix/b");
+    assert_eq!(
+        merge_runpath("/nix/a:/nix/b", Some("$ORIGIN:/nix/a")),
+        "/nix/a:/nix/b:$ORIGIN"
+    );
+    assert_eq!(merge_runpath("/nix/a::", Some("")), "/nix/a");
+}
+
+#[test]
+fn patch_leaves_static_libraries_alone() {
+    let dir = scratch_dir("patch-static");
+    let path = dir.join("libtest.so");
+    let original = build(Format {
+        is_64: true,
+        little_endian: true,
+    });
+    fs::write(&path, &original).unwrap();
+
+    let changes = Patch {
+        interpreter: Some("/nix/store/glibc/lib/ld-linux-x86-64.so.2"),
+        runpath: Some("/nix/store/nss/lib"),
+    };
+    assert!(!patch(&path, changes).unwrap());
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+/// Point the test binary at its own interpreter through a longer path and
+/// give it a runpath; it must still run, and patching again is a no-op.
+#[test]
+fn patched_binary_still_runs() {
+    let dir = scratch_dir("patch-exe");
+    let path = dir.join("tests");
+    fs::copy(env::current_exe().unwrap(), &path).unwrap();
+
+    let elf = Elf::read(&mut File::open(&path).unwrap()).unwrap();
+    let Some(interpreter) = elf.interpreter(&mut File::open(&path).unwrap()).unwrap() else {
+        // statically linked, nothing to patch
+        return;
+    };
+    let long_interpreter = dir.join("a-much-longer-name-than-any-fhs-dynamic-linker.so");
+    std::os::unix::fs::symlink(&interpreter, &long_interpreter).unwrap();
+    let long_interpreter = long_interpreter.to_str().unwrap();
+    let lib_dir = dir.join("lib");
+    fs::create_dir_all(&lib_dir).unwrap();
+    let runpath = lib_dir.to_str().unwrap();
+
+    let changes = Patch {
+        interpreter: Some(long_interpreter),
+        runpath: Some(runpath),
+    };
+    assert!(patch(&path, changes).unwrap());
+    assert!(!dir.join("tests.patch").exists());
+
+    let mut file = File::open(&path).unwrap();
+    let elf = Elf::read(&mut file).unwrap();
+    assert_eq!(
+        elf.interpreter(&mut file).unwrap().as_deref(),
+        Some(long_interpreter)
+    );
+    let dynamic = elf.dynamic(&mut file).unwrap().unwrap();
+    assert!(
+        dynamic.runpath().unwrap().unwrap().starts_with(runpath),
+        "{:?}",
+        dynamic.runpath()
+    );
+    drop(file);
+
+    let output = Command::new(&path)
+        .args(["--list", "elf::tests::rejects_non_elf"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("rejects_non_elf"));
+
+    assert!(!patch(&path, changes).unwrap());
+}
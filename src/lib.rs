@@ -1,18 +1,25 @@
+#[cfg(target_os = "linux")]
+mod elf;
 mod loader;
 mod logger;
 mod panic;
 mod self_path;
+#[cfg(test)]
+mod test_util;
 mod updater;
 
-use std::{cell::Cell, ffi::CString, fs, os::raw::c_int, ptr};
+use std::{cell::Cell, ffi::CString, fs, os::raw::c_int, ptr, time::Duration};
 
 use classicube_helpers::{async_manager, chat::print};
 use classicube_sys::{
-    Chat_AddOf, IGameComponent, MsgType_MSG_TYPE_CLIENTSTATUS_2, OwnedString, Server,
-    String_AppendConst,
+    Chat_AddOf, IGameComponent, MsgType, MsgType_MSG_TYPE_ANNOUNCEMENT,
+    MsgType_MSG_TYPE_CLIENTSTATUS_2, OwnedString, Server, String_AppendConst,
 };
 use tracing::*;
 
+/// How long `free` waits for a cancelled update to clean up after itself.
+const UPDATE_CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
 thread_local!(
     static INIT_ONCE: Cell<bool> = const { Cell::new(false) };
     static UPDATER_STARTED: Cell<bool> = const { Cell::new(false) };
@@ -46,6 +53,12 @@ extern "C" fn init() {
 extern "C" fn free() {
     debug!("Free");
 
+    // stop a running update while the runtime it runs on is still there, so
+    // it can remove its half-written files
+    if !updater::cancel::cancel_and_wait(UPDATE_CANCEL_TIMEOUT) {
+        warn!("update didn't stop within {UPDATE_CANCEL_TIMEOUT:?}, shutting down anyway");
+    }
+
     // Forward Free to the inner plugin so it can release D3D9 GPU
     // resources (OwnedGfxTexture / OwnedGfxVertexBuffer) before
     // ClassiCube's Gfx_Free runs. Without this, the D3D9 device
@@ -80,21 +93,67 @@ extern "C" fn on_new_map_loaded() {
 
     if UPDATER_STARTED.get() {
         loader::on_new_map_loaded();
+
+        if updater::loader_restart_required() {
+            print(format!(
+                "{}Restart ClassiCube to finish updating the CEF Loader",
+                classicube_helpers::color::GOLD
+            ));
+        }
     } else {
         UPDATER_STARTED.set(true);
 
+        updater::ui::start();
+        updater::events::start_logging();
+
+        // before cleanup, which would take the previous versions for leftovers
+        match updater::rollback::recover() {
+            Ok(true) => {
+                warn!("the last update crashed while loading, rolled it back");
+                print(format!(
+                    "{}The last CEF update crashed while loading, went back to the previous version",
+                    classicube_helpers::color::GOLD
+                ));
+            }
+            Ok(false) => {}
+            Err(e) => warn!("couldn't roll back the last update: {:#}", e),
+        }
+
+        let cancel = updater::cancel::token();
         async_manager::spawn(async move {
+            let _running = updater::cancel::running();
+
+            if let Err(e) = updater::cleanup::run().await {
+                warn!("cleanup failed: {:#}", e);
+            }
+
             // don't update if debug build
             if cfg!(not(debug_assertions))
-                && let Err(e) = updater::update_plugins().await
+                && let Err(e) = updater::update_plugins(&cancel).await
             {
+                if updater::cancel::is_cancelled(&e) {
+                    debug!("update cancelled");
+                    return;
+                }
+                error!("{:#?}", e);
+                updater::events::emit(updater::events::UpdateEvent::Failed {
+                    component: "CEF".to_string(),
+                    error: e.to_string(),
+                });
+            }
+
+            // catches files deleted or damaged since they were installed,
+            // before CEF trips over them
+            if let Err(e) = updater::cef_binary::repair(&cancel).await {
+                if updater::cancel::is_cancelled(&e) {
+                    debug!("repair cancelled");
+                    return;
+                }
                 error!("{:#?}", e);
-                print_async(format!(
-                    "{}Failed to update CEF: {}{e}",
-                    classicube_helpers::color::RED,
-                    classicube_helpers::color::WHITE
-                ))
-                .await;
+                updater::events::emit(updater::events::UpdateEvent::Failed {
+                    component: "CEF Binary".to_string(),
+                    error: e.to_string(),
+                });
             }
 
             async_manager::spawn_on_main_thread(async move {
@@ -127,7 +186,7 @@ pub static mut Plugin_Component: IGameComponent = IGameComponent {
     next: ptr::null_mut(),
 };
 
-pub fn status<S: Into<String>>(s: S) {
+fn add_message_of<S: Into<String>>(s: S, msg_type: MsgType) {
     let mut s = s.into();
     info!("{}", s);
 
@@ -138,16 +197,15 @@ pub fn status<S: Into<String>>(s: S) {
     let owned_string = OwnedString::new(s);
 
     unsafe {
-        Chat_AddOf(
-            owned_string.as_cc_string(),
-            MsgType_MSG_TYPE_CLIENTSTATUS_2 as _,
-        );
+        Chat_AddOf(owned_string.as_cc_string(), msg_type as _);
     }
 }
 
-pub async fn print_async<S: Into<String> + Send + 'static>(s: S) {
-    async_manager::run_on_main_thread(async move {
-        print(s);
-    })
-    .await;
+pub fn status<S: Into<String>>(s: S) {
+    add_message_of(s, MsgType_MSG_TYPE_CLIENTSTATUS_2);
+}
+
+/// Large centered text, for things the player must not miss.
+pub fn announce<S: Into<String>>(s: S) {
+    add_message_of(s, MsgType_MSG_TYPE_ANNOUNCEMENT);
 }
@@ -0,0 +1,224 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    iter,
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use tracing::*;
+
+/// `LC_CTYPE` to run CEF with, which decides how keys map to characters: the
+/// default `C` fixes layouts like Finnish typing `¥` for `å`, but breaks
+/// others. Set it to the locale your layout needs, or empty to keep the
+/// game's.
+pub const LC_CTYPE_ENV: &str = "CEF_LOADER_LC_CTYPE";
+
+const DEFAULT_LC_CTYPE: &str = "C";
+
+/// `None` if `LC_CTYPE` should be left alone.
+pub fn lc_ctype_from_env() -> Option<String> {
+    match env::var(LC_CTYPE_ENV) {
+        Ok(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+        Err(_) => Some(DEFAULT_LC_CTYPE.to_string()),
+    }
+}
+
+/// `dir` first in a `PATH`-style value, ahead of (and instead of any other
+/// copy of) what it held.
+pub fn prepended_path(existing: Option<OsString>, dir: &Path) -> Result<OsString> {
+    let rest = existing
+        .iter()
+        .flat_map(env::split_paths)
+        .filter(|path| !path.as_os_str().is_empty() && path != dir);
+    Ok(env::join_paths(iter::once(dir.to_path_buf()).chain(rest))?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvChange {
+    pub name: String,
+    /// Before the loader first changed it.
+    pub previous: Option<OsString>,
+}
+
+/// Where `EnvChanges` reads and writes variables.
+pub trait Env {
+    fn var_os(&self, name: &str) -> Option<OsString>;
+    fn set_var(&mut self, name: &str, value: &OsStr);
+    fn remove_var(&mut self, name: &str);
+}
+
+/// The game process' own environment.
+#[derive(Debug, Default)]
+pub struct ProcessEnv;
+
+impl Env for ProcessEnv {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        env::var_os(name)
+    }
+
+    fn set_var(&mut self, name: &str, value: &OsStr) {
+        unsafe {
+            env::set_var(name, value);
+        }
+    }
+
+    fn remove_var(&mut self, name: &str) {
+        unsafe {
+            env::remove_var(name);
+        }
+    }
+}
+
+/// Every variable the loader changed in the game's own environment, so they
+/// can be listed and put back.
+#[derive(Debug, Default)]
+pub struct EnvChanges<E = ProcessEnv> {
+    env: E,
+    changes: Vec<EnvChange>,
+}
+
+impl EnvChanges {
+    pub const fn new() -> Self {
+        Self::with_env(ProcessEnv)
+    }
+}
+
+impl<E: Env> EnvChanges<E> {
+    pub const fn with_env(env: E) -> Self {
+        Self {
+            env,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn changes(&self) -> &[EnvChange] {
+        &self.changes
+    }
+
+    pub fn set(&mut self, name: &str, value: impl AsRef<OsStr>) {
+        let value = value.as_ref();
+        if !self.changes.iter().any(|change| change.name == name) {
+            self.changes.push(EnvChange {
+                name: name.to_string(),
+                previous: self.env.var_os(name),
+            });
+        }
+        debug!("set {}={:?}", name, value);
+        self.env.set_var(name, value);
+    }
+
+    /// Only reaches processes started afterwards; our own library search was
+    /// fixed when the game started.
+    pub fn prepend_path(&mut self, name: &str, dir: &Path) -> Result<()> {
+        let value =
+            prepended_path(self.env.var_os(name), dir).with_context(|| format!("set {name}"))?;
+        self.set(name, value);
+        Ok(())
+    }
+
+    /// Put back everything `set` changed, most recent first.
+    pub fn restore(&mut self) {
+        for change in self.changes.drain(..).rev() {
+            debug!("restoring {}={:?}", change.name, change.previous);
+            match &change.previous {
+                Some(previous) => self.env.set_var(&change.name, previous),
+                None => self.env.remove_var(&change.name),
+            }
+        }
+    }
+}
+
+/// Changes made for the inner plugin, undone when it's freed. Only Linux can
+/// keep the helper's variables out of here (see `helper_script`); Windows'
+/// `PATH` and macOS' `DYLD_LIBRARY_PATH` still go through the game's
+/// environment.
+pub static CHANGES: Mutex<EnvChanges> = Mutex::new(EnvChanges::new());
+
+/// A variable only the `cef` helper gets, on top of the game's environment.
+/// Linux only: Windows finds `cef.exe` and its `libcef.dll` through the game's
+/// `PATH`, and macOS drops `DYLD_*` from anything a wrapper script starts.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelperVar {
+    pub name: String,
+    pub value: OsString,
+    /// Put `value` in front of the inherited `PATH`-style value instead of
+    /// replacing it.
+    pub prepend: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl HelperVar {
+    pub fn set(name: &str, value: impl Into<OsString>) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.into(),
+            prepend: false,
+        }
+    }
+
+    pub fn prepend(name: &str, dir: &Path) -> Self {
+        Self {
+            prepend: true,
+            ..Self::set(name, dir)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn shell_quote(value: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut quoted = vec![b'\''];
+    for &byte in value.as_bytes() {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// A `sh` script that runs `exe` with `vars`, so the helper gets its
+/// environment without the game's changing.
+#[cfg(target_os = "linux")]
+pub fn helper_script(exe: &Path, vars: &[HelperVar]) -> Vec<u8> {
+    let mut script = b"#!/bin/sh\n".to_vec();
+    for var in vars {
+        script.extend_from_slice(format!("export {}=", var.name).as_bytes());
+        script.extend(shell_quote(&var.value));
+        if var.prepend {
+            script.extend_from_slice(format!("\"${{{0}:+:${0}}}\"", var.name).as_bytes());
+        }
+        script.push(b'\n');
+    }
+    script.extend_from_slice(b"exec ");
+    script.extend(shell_quote(exe.as_os_str()));
+    script.extend_from_slice(b" \"$@\"\n");
+    script
+}
+
+/// Write `helper_script` to `path`, replacing whatever is there even while
+/// it's running.
+#[cfg(target_os = "linux")]
+pub fn write_helper_script(path: &Path, exe: &Path, vars: &[HelperVar]) -> Result<()> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let mut new_name = path.file_name().unwrap_or_default().to_os_string();
+    new_name.push("-new");
+    let new_path = path.with_file_name(new_name);
+
+    fs::write(&new_path, helper_script(exe, vars))
+        .with_context(|| format!("write {new_path:?}"))?;
+    fs::set_permissions(&new_path, fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("chmod {new_path:?}"))?;
+    fs::rename(&new_path, path).with_context(|| format!("rename {new_path:?} -> {path:?}"))?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use super::*;
+
+fn split(value: &OsString) -> Vec<PathBuf> {
+    env::split_paths(value).collect()
+}
+
+#[test]
+fn prepended_path_goes_first() {
+    let dir = env::temp_dir().join("cef_binary");
+    let other = env::temp_dir().join("lib");
+    let existing = env::join_paths([&other, &dir]).unwrap();
+
+    let value = prepended_path(Some(existing), &dir).unwrap();
+
+    assert_eq!(split(&value), [dir, other]);
+}
+
+#[test]
+fn prepended_path_when_unset_or_empty() {
+    let dir = env::temp_dir().join("cef_binary");
+    for existing in [None, Some(OsString::new())] {
+        let value = prepended_path(existing, &dir).unwrap();
+        assert_eq!(split(&value), [dir.as_path()]);
+    }
+}
+
+/// Keeps the tests off the process environment, which other threads read.
+impl Env for HashMap<String, OsString> {
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        self.get(name).cloned()
+    }
+
+    fn set_var(&mut self, name: &str, value: &OsStr) {
+        self.insert(name.to_string(), value.to_os_string());
+    }
+
+    fn remove_var(&mut self, name: &str) {
+        self.remove(name);
+    }
+}
+
+#[test]
+fn restore_puts_back_previous_values() {
+    let env = HashMap::from([("SET".to_string(), OsString::from("before"))]);
+
+    let mut changes = EnvChanges::with_env(env);
+    changes.set("SET", "first");
+    changes.set("SET", "second");
+    changes.set("UNSET", "new");
+    assert_eq!(changes.env.var_os("SET").unwrap(), "second");
+    assert_eq!(
+        changes.changes(),
+        [
+            EnvChange {
+                name: "SET".to_string(),
+                previous: Some("before".into()),
+            },
+            EnvChange {
+                name: "UNSET".to_string(),
+                previous: None,
+            },
+        ]
+    );
+
+    changes.restore();
+
+    assert_eq!(changes.env.var_os("SET").unwrap(), "before");
+    assert!(changes.env.var_os("UNSET").is_none());
+    assert!(changes.changes().is_empty());
+}
+
+#[test]
+fn prepend_path_reads_the_same_env() {
+    let dir = env::temp_dir().join("cef_binary");
+    let other = env::temp_dir().join("lib");
+    let env = HashMap::from([("PATH".to_string(), env::join_paths([&other]).unwrap())]);
+
+    let mut changes = EnvChanges::with_env(env);
+    changes.prepend_path("PATH", &dir).unwrap();
+
+    assert_eq!(split(&changes.env.var_os("PATH").unwrap()), [dir, other]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn helper_script_quotes_and_prepends() {
+    let script = helper_script(
+        Path::new("/games/it's/cef/cef_linux_x86_64"),
+        &[
+            HelperVar::prepend("LD_LIBRARY_PATH", Path::new("/games/cef/cef_binary")),
+            HelperVar::set("LC_CTYPE", "C"),
+        ],
+    );
+
+    assert_eq!(
+        String::from_utf8(script).unwrap(),
+        "#!/bin/sh\n\
+         export LD_LIBRARY_PATH='/games/cef/cef_binary'\"${LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}\"\n\
+         export LC_CTYPE='C'\n\
+         exec '/games/it'\\''s/cef/cef_linux_x86_64' \"$@\"\n"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn helper_script_runs_exe_with_vars() {
+    use std::{fs, process::Command};
+
+    let dir = crate::test_util::scratch_dir("helper");
+    let helper_path = dir.join("cef");
+
+    write_helper_script(
+        &helper_path,
+        Path::new("/usr/bin/env"),
+        &[
+            HelperVar::prepend("CEF_LOADER_TEST_PATH", Path::new("/first")),
+            HelperVar::set("LC_CTYPE", "fi_FI.UTF-8"),
+        ],
+    )
+    .unwrap();
+    let output = Command::new(&helper_path)
+        .env("CEF_LOADER_TEST_PATH", "/second")
+        .output()
+        .unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.contains("CEF_LOADER_TEST_PATH=/first:/second\n"));
+    assert!(output.contains("LC_CTYPE=fi_FI.UTF-8\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,11 +1,13 @@
+pub mod environment;
 mod plugin;
 
 use std::cell::Cell;
 
+use anyhow::{Error, Result};
 use classicube_sys::IGameComponent;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::print;
+use crate::{print, updater::rollback};
 
 thread_local!(
     static PLUGIN: Cell<Option<*mut IGameComponent>> = const { Cell::new(None) };
@@ -17,7 +19,29 @@ pub fn init() {
         return;
     }
 
-    match plugin::try_init() {
+    // if Init takes the game down, the next start rolls the update back
+    if let Err(e) = rollback::loading() {
+        warn!("couldn't mark the update as loading: {:#}", e);
+    }
+
+    let result = plugin::try_init().or_else(|e| {
+        if plugin::loaded_anything() {
+            // the new libcef or plugin is already in the process (and
+            // locked on Windows), so leave the files alone: the journal is
+            // still marked as loading, which rolls it back at the next start
+            if let Ok(Some(_)) = rollback::pending() {
+                warn!("couldn't load the updated plugin, rolling back at restart");
+                print(format!(
+                    "{}Couldn't load the updated CEF, restart ClassiCube to go back to the previous version",
+                    classicube_helpers::color::GOLD
+                ));
+            }
+            return Err(e);
+        }
+        retry_previous(e)
+    });
+
+    match result {
         Ok(plugin_component) => {
             PLUGIN.with(|cell| cell.set(Some(plugin_component)));
 
@@ -29,6 +53,10 @@ pub fn init() {
                     f();
                 }
             }
+
+            if let Err(e) = rollback::commit() {
+                warn!("couldn't commit the update: {:#}", e);
+            }
         }
 
         Err(e) => {
@@ -43,6 +71,26 @@ pub fn init() {
     }
 }
 
+/// Nothing was loaded yet, so the previous version can be put back and loaded
+/// right away.
+fn retry_previous(e: Error) -> Result<*mut IGameComponent> {
+    match rollback::revert() {
+        Ok(true) => {
+            warn!("couldn't load the updated plugin, rolled it back: {:#}", e);
+            print(format!(
+                "{}Couldn't load the updated CEF, went back to the previous version",
+                classicube_helpers::color::GOLD
+            ));
+            plugin::try_init()
+        }
+        Ok(false) => Err(e),
+        Err(revert_error) => {
+            warn!("couldn't roll back the update: {:#}", revert_error);
+            Err(e)
+        }
+    }
+}
+
 pub fn free() {
     // Forward `IGameComponent::Free` to the inner plugin so it can run
     // its shutdown (notably `entity_manager.shutdown()`, which drops
@@ -71,6 +119,9 @@ pub fn free() {
             }
         }
     });
+
+    // the helper processes are gone with CEF
+    environment::CHANGES.lock().unwrap().restore();
 }
 
 pub fn reset() {
@@ -1,10 +1,25 @@
-use std::{env, ffi::CString, fs, os::raw::c_void, path::Path};
+use std::{
+    env,
+    ffi::CString,
+    fs,
+    os::raw::c_void,
+    path::{self, Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use anyhow::{Context, Result, bail};
 use classicube_helpers::time;
 use classicube_sys::{DynamicLib_Get2, DynamicLib_Load2, IGameComponent, OwnedString};
 
-use crate::updater::{CEF_EXE_PATH, CEF_PLUGIN_PATH, cef_binary::CEF_BINARY_PATH};
+use super::environment;
+use crate::updater::{
+    CEF_EXE_PATH, CEF_PLUGIN_PATH,
+    cef_binary::{
+        self, CEF_BINARY_PATH,
+        system::{SYSTEM_CEF_ENV, SystemCef},
+    },
+    compat::{self, Compat},
+};
 
 fn get_error() -> String {
     #[cfg(windows)]
@@ -19,6 +34,15 @@ fn get_error() -> String {
     }
 }
 
+/// Set once any library has been loaded; nothing is ever unloaded.
+static LOADED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a failed `try_init` got as far as loading libcef or the plugin. A
+/// second attempt would get those same modules back, whatever is on disk now.
+pub fn loaded_anything() -> bool {
+    LOADED.load(Ordering::SeqCst)
+}
+
 fn dll_load(path: &str) -> Result<*mut c_void> {
     let path = OwnedString::new(path);
 
@@ -26,10 +50,28 @@ fn dll_load(path: &str) -> Result<*mut c_void> {
     if ptr.is_null() {
         bail!(get_error());
     }
+    LOADED.store(true, Ordering::SeqCst);
 
     Ok(ptr)
 }
 
+/// Load CEF from `cef_binary_path` by absolute path before the inner plugin
+/// does, so its import of libcef resolves to the copy already loaded rather
+/// than one found on the library search path.
+fn preload_cef(cef_binary_path: &Path) -> Result<()> {
+    for name in cef_binary::CEF_PRELOAD_LIBRARIES {
+        let path = cef_binary_path.join(name);
+        let path = path
+            .to_str()
+            .with_context(|| format!("non UTF-8 path {path:?}"))?;
+        time!("dll_load", 5000, {
+            tracing::debug!("dll_load {}", path);
+            dll_load(path).with_context(|| format!("couldn't load {path}"))?
+        });
+    }
+    Ok(())
+}
+
 fn dll_get(library: *mut c_void, symbol_name: &str) -> Result<*mut c_void> {
     let symbol_name = CString::new(symbol_name)?;
 
@@ -42,12 +84,49 @@ fn dll_get(library: *mut c_void, symbol_name: &str) -> Result<*mut c_void> {
 }
 
 pub fn try_init() -> Result<*mut IGameComponent> {
+    // libcef and its resources: a system CEF if one is configured, else ours
+    let (cef_binary_path, cef_binary_version) = match SystemCef::from_env()? {
+        Some(system_cef) => (system_cef.dir, Some(system_cef.version)),
+        None => (
+            PathBuf::from(CEF_BINARY_PATH),
+            cef_binary::get_current_version(),
+        ),
+    };
+    // nothing below may depend on the working directory staying put
+    let cef_binary_path = path::absolute(&cef_binary_path)
+        .with_context(|| format!("absolute path of {cef_binary_path:?}"))?;
+    // on PATH for running the helper; macOS runs it from the .app bundles
+    #[cfg(not(target_os = "macos"))]
+    let cef_dir = path::absolute(Path::new(CEF_EXE_PATH.as_str()).parent().unwrap())
+        .context("absolute path of cef/")?;
+    let cef_plugin_path = path::absolute(CEF_PLUGIN_PATH.as_str())
+        .with_context(|| format!("absolute path of {}", *CEF_PLUGIN_PATH))?;
+
+    // refuse combinations that would otherwise crash somewhere inside libcef
+    if let Some(compat) = Compat::load_installed()? {
+        compat
+            .check(compat::LOADER_VERSION, cef_binary_version.as_deref())
+            .with_context(|| {
+                if env::var_os(SYSTEM_CEF_ENV).is_some() {
+                    format!("system CEF in {cef_binary_path:?} ({SYSTEM_CEF_ENV})")
+                } else {
+                    format!("CEF binary in {CEF_BINARY_PATH}")
+                }
+            })?;
+    }
+
+    // everything we change in the game's environment, put back on Free
+    let mut env_changes = environment::CHANGES.lock().unwrap();
+
     #[cfg(target_os = "windows")]
     {
         // copy cef_windows_x86_64.exe to cef.exe
         if let Err(e) = fs::copy(
-            CEF_EXE_PATH,
-            Path::new(CEF_EXE_PATH).parent().unwrap().join("cef.exe"),
+            CEF_EXE_PATH.as_str(),
+            Path::new(CEF_EXE_PATH.as_str())
+                .parent()
+                .unwrap()
+                .join("cef.exe"),
         )
         .context("couldn't copy cef exe")
         {
@@ -60,12 +139,11 @@ pub fn try_init() -> Result<*mut IGameComponent> {
             ));
         }
 
-        // add cef/cef_binary and cef/ to PATH so that cef.dll is found,
-        // and cef.exe can run
-        let path = env::var("PATH").unwrap_or_default();
-        unsafe {
-            env::set_var("PATH", format!("{};{};{}", path, CEF_BINARY_PATH, "cef"));
-        }
+        // put cef/cef_binary and cef/ first in PATH so that cef.exe runs and
+        // finds our libcef.dll; unlike on Linux this is the whole game's PATH,
+        // since a helper exe can't be wrapped in a script here
+        env_changes.prepend_path("PATH", &cef_dir)?;
+        env_changes.prepend_path("PATH", &cef_binary_path)?;
     }
 
     #[cfg(target_os = "linux")]
@@ -73,34 +151,38 @@ pub fn try_init() -> Result<*mut IGameComponent> {
         use std::os::unix::fs::PermissionsExt;
 
         // make it executable
-        let mut perms = fs::metadata(CEF_EXE_PATH)
-            .with_context(|| format!("{CEF_EXE_PATH} not found?"))?
+        let mut perms = fs::metadata(CEF_EXE_PATH.as_str())
+            .with_context(|| format!("{} not found?", *CEF_EXE_PATH))?
             .permissions();
         perms.set_mode(0o755);
-        fs::set_permissions(CEF_EXE_PATH, perms)?;
-
-        // copy cef_linux_x86_64 to cef
-        let new_exe_path = Path::new(CEF_EXE_PATH).parent().unwrap().join("cef");
-        fs::copy(CEF_EXE_PATH, new_exe_path).context("couldn't copy cef exe")?;
-
-        // add cef/cef_binary to LD_LIBRARY_PATH so that libcef.so is found
-        unsafe {
-            if let Ok(ld_library_path) = env::var("LD_LIBRARY_PATH") {
-                env::set_var(
-                    "LD_LIBRARY_PATH",
-                    format!("{}:{}", ld_library_path, CEF_BINARY_PATH),
-                );
-            } else {
-                env::set_var("LD_LIBRARY_PATH", CEF_BINARY_PATH);
-            }
-
-            // add ./cef/ to path so that we can run "cef"
-            let path = env::var("PATH").unwrap_or_default();
-            env::set_var("PATH", format!("{}:{}", path, "cef"));
-
-            // fix linux keyboard language layout mapping
-            // on finnish keyboard: US [ is typed as ¥, but is suppose to be å
-            env::set_var("LC_CTYPE", "C");
+        fs::set_permissions(CEF_EXE_PATH.as_str(), perms)?;
+
+        // fix linux keyboard language layout mapping
+        // on finnish keyboard: US [ is typed as ¥, but is suppose to be å
+        let lc_ctype = environment::lc_ctype_from_env();
+
+        // "cef" runs cef_linux_x86_64 with what it needs: our libcef.so first
+        // in LD_LIBRARY_PATH, which the game itself doesn't need since
+        // `preload_cef`
+        let mut helper_vars = vec![environment::HelperVar::prepend(
+            "LD_LIBRARY_PATH",
+            &cef_binary_path,
+        )];
+        if let Some(lc_ctype) = &lc_ctype {
+            helper_vars.push(environment::HelperVar::set("LC_CTYPE", lc_ctype));
+        }
+        let exe_path = path::absolute(CEF_EXE_PATH.as_str())
+            .with_context(|| format!("absolute path of {}", *CEF_EXE_PATH))?;
+        environment::write_helper_script(&cef_dir.join("cef"), &exe_path, &helper_vars)
+            .context("couldn't write cef helper")?;
+
+        // put cef/ first in PATH so that we can run "cef"
+        env_changes.prepend_path("PATH", &cef_dir)?;
+
+        // the browser itself runs in the game process and maps keys there,
+        // so this one has to be the game's, see `LC_CTYPE_ENV`
+        if let Some(lc_ctype) = &lc_ctype {
+            env_changes.set("LC_CTYPE", lc_ctype);
         }
     }
 
@@ -109,12 +191,12 @@ pub fn try_init() -> Result<*mut IGameComponent> {
         use std::{io::Write, os::unix::fs::PermissionsExt};
 
         // make it executable
-        let mut perms = fs::metadata(CEF_EXE_PATH)?.permissions();
+        let mut perms = fs::metadata(CEF_EXE_PATH.as_str())?.permissions();
         perms.set_mode(0o755);
-        fs::set_permissions(CEF_EXE_PATH, perms)?;
+        fs::set_permissions(CEF_EXE_PATH.as_str(), perms)?;
 
         // trying to link with dlopen will just hang the window
-        let dll_path = Path::new(CEF_BINARY_PATH).join("Chromium Embedded Framework");
+        let dll_path = cef_binary_path.join(cef_binary::CEF_LIBRARY_NAME);
         if !fs::metadata(&dll_path)
             .map(|m| m.is_file())
             .unwrap_or(false)
@@ -133,7 +215,7 @@ pub fn try_init() -> Result<*mut IGameComponent> {
         ] {
             fs::create_dir_all(format!("./cef/{}.app/Contents/MacOS", app_name))?;
             fs::copy(
-                CEF_EXE_PATH,
+                CEF_EXE_PATH.as_str(),
                 format!("./cef/{}.app/Contents/MacOS/{}", app_name, app_name),
             )
             .context("couldn't copy cef exe")?;
@@ -148,27 +230,32 @@ pub fn try_init() -> Result<*mut IGameComponent> {
             )?;
         }
 
-        // add "cef/Chromium Embedded Framework.framework/Libraries" to DYLD_LIBRARY_PATH
+        // put "cef/Chromium Embedded Framework.framework/Libraries" first in DYLD_LIBRARY_PATH
         // so that libGLESv2.dylib/libEGL.dylib/libvk_swiftshader.dylib are found
         // ERROR:gl_implementation.cc(501)] Failed to load /cc/cef/cef.app/Contents/MacOS/libGLESv2.dylib:
         // dlopen(/cc/cef/cef.app/Contents/MacOS/libGLESv2.dylib, 0x0001):
         // tried: '/cc/cef/cef.app/Contents/MacOS/libGLESv2.dylib' (no such file)
-        let libraries_path = format!("{}/Libraries", CEF_BINARY_PATH);
-        unsafe {
-            if let Ok(dyld_library_path) = env::var("DYLD_LIBRARY_PATH") {
-                env::set_var(
-                    "DYLD_LIBRARY_PATH",
-                    format!("{}:{}", dyld_library_path, libraries_path),
-                );
-            } else {
-                env::set_var("DYLD_LIBRARY_PATH", format!("{}", libraries_path));
-            }
-        }
+        // This is the whole game's: a `/bin/sh` wrapper like Linux's would
+        // have DYLD_* stripped by System Integrity Protection.
+        env_changes.prepend_path("DYLD_LIBRARY_PATH", &cef_binary_path.join("Libraries"))?;
     }
 
+    let changed = env_changes
+        .changes()
+        .iter()
+        .map(|change| change.name.as_str())
+        .collect::<Vec<_>>();
+    tracing::debug!("changed environment: {}", changed.join(", "));
+    drop(env_changes);
+
+    preload_cef(&cef_binary_path)?;
+
+    let cef_plugin_path = cef_plugin_path
+        .to_str()
+        .with_context(|| format!("non UTF-8 path {cef_plugin_path:?}"))?;
     let library = time!("dll_load", 5000, {
-        tracing::debug!("dll_load {}", CEF_PLUGIN_PATH);
-        dll_load(CEF_PLUGIN_PATH)?
+        tracing::debug!("dll_load {}", cef_plugin_path);
+        dll_load(cef_plugin_path)?
     });
 
     let plugin_component = dll_get(library, "Plugin_Component")?;
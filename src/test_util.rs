@@ -0,0 +1,19 @@
+use std::{env, fs, panic::Location, path::PathBuf, process};
+
+/// A fresh, empty directory for a test, named after the calling test file
+/// and `name` so tests running in parallel never share one.
+#[track_caller]
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let caller = Location::caller().file().replace('\\', "/");
+    let module = caller
+        .rsplit_once("src/")
+        .map_or(caller.as_str(), |(_, module)| module)
+        .trim_end_matches(".rs")
+        .trim_end_matches("/tests")
+        .replace('/', "-");
+
+    let dir = env::temp_dir().join(format!("cef-loader-{module}-{}-{name}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    error, fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Condvar, LazyLock, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// Cancelled by `free` when the game closes, so a running update stops and
+/// cleans up before the async runtime is shut down under it.
+static TOKEN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// Whether an update run is in progress, for `cancel_and_wait`.
+static RUNNING: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+pub fn token() -> CancellationToken {
+    TOKEN.clone()
+}
+
+/// Held for the duration of an update run.
+pub struct Running(());
+
+pub fn running() -> Running {
+    *RUNNING.0.lock().unwrap() = true;
+    Running(())
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        *RUNNING.0.lock().unwrap() = false;
+        RUNNING.1.notify_all();
+    }
+}
+
+/// Cancel any update in progress and give it up to `timeout` to remove its
+/// temporary files. Returns `false` if it was still running after that.
+pub fn cancel_and_wait(timeout: Duration) -> bool {
+    TOKEN.cancel();
+
+    let (running, finished) = &RUNNING;
+    let (_running, result) = finished
+        .wait_timeout_while(running.lock().unwrap(), timeout, |running| *running)
+        .unwrap();
+    !result.timed_out()
+}
+
+/// The error an update stops with once cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("update cancelled")
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// Whether `e` is (or was caused by) a cancellation, which isn't worth
+/// reporting as a failure.
+pub fn is_cancelled(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.is::<Cancelled>()
+            || cause
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+                .is_some_and(|inner| inner.is::<Cancelled>())
+    })
+}
+
+/// `Err(Cancelled)` if `token` has been cancelled.
+pub fn check(token: &CancellationToken) -> Result<(), Cancelled> {
+    if token.is_cancelled() {
+        Err(Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// `future`, cancelling `siblings` if it fails. Running downloads side by
+/// side with `join!` and this, instead of `try_join!`, lets the others stop
+/// and remove their temporary files rather than being dropped halfway.
+pub async fn or_cancel<T>(
+    siblings: &CancellationToken,
+    future: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let result = future.await;
+    if result.is_err() {
+        siblings.cancel();
+    }
+    result
+}
+
+/// Both results of an `or_cancel` pair, or the error that stopped them rather
+/// than the cancellation it caused.
+pub fn both<A, B>(a: anyhow::Result<A>, b: anyhow::Result<B>) -> anyhow::Result<(A, B)> {
+    match (a, b) {
+        (Ok(a), Ok(b)) => Ok((a, b)),
+        (Err(e), Ok(_)) | (Ok(_), Err(e)) => Err(e),
+        (Err(a), Err(b)) => Err(if is_cancelled(&a) { b } else { a }),
+    }
+}
+
+/// `both` for any number of `or_cancel` results.
+pub fn all<T>(results: Vec<anyhow::Result<T>>) -> anyhow::Result<Vec<T>> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut error: Option<anyhow::Error> = None;
+    for result in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => {
+                if error.as_ref().is_none_or(is_cancelled) {
+                    error = Some(e);
+                }
+            }
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(values),
+    }
+}
+
+/// Reader that fails with `Cancelled` as soon as the token is cancelled, even
+/// while waiting on a slow download, so everything reading from it (the
+/// decompressor and, through the pipe, the extractor) stops promptly.
+pub struct Cancellable<R> {
+    inner: R,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl<R> Cancellable<R> {
+    pub fn new(inner: R, token: CancellationToken) -> Self {
+        Self {
+            inner,
+            cancelled: Box::pin(token.cancelled_owned()),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Cancellable<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(io::Error::other(Cancelled)));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
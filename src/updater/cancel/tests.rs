@@ -0,0 +1,75 @@
+use std::cell::Cell;
+
+use anyhow::Context as _;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::*;
+
+#[tokio::test]
+async fn cancellable_stops_a_pending_read() {
+    let (mut writer, reader) = tokio::io::duplex(64);
+    let token = CancellationToken::new();
+    let mut reader = Cancellable::new(reader, token.clone());
+
+    writer.write_all(b"cef").await.unwrap();
+    let mut buf = [0; 3];
+    reader.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"cef");
+
+    // nothing more is coming, so this only returns because of the cancel
+    let read = tokio::spawn(async move { reader.read(&mut buf).await });
+    token.cancel();
+    let e = read.await.unwrap().unwrap_err();
+
+    let e = anyhow::Error::new(e).context("download");
+    assert!(is_cancelled(&e), "{e:#}");
+}
+
+#[test]
+fn only_cancellation_counts() {
+    assert!(is_cancelled(
+        &anyhow::Error::new(Cancelled).context("update")
+    ));
+    assert!(!is_cancelled(&anyhow::anyhow!("update cancelled")));
+
+    let io = Err::<(), _>(io::Error::other("connection reset")).context("download");
+    assert!(!is_cancelled(&io.unwrap_err()));
+
+    let token = CancellationToken::new();
+    assert_eq!(check(&token), Ok(()));
+    token.cancel();
+    assert_eq!(check(&token), Err(Cancelled));
+}
+
+#[tokio::test]
+async fn a_failure_stops_its_siblings() {
+    let siblings = CancellationToken::new();
+    let cleaned_up = Cell::new(false);
+
+    let (failed, stopped) = tokio::join!(
+        or_cancel(&siblings, async { Err::<(), _>(anyhow::anyhow!("404")) }),
+        or_cancel(&siblings, async {
+            siblings.cancelled().await;
+            cleaned_up.set(true);
+            Err::<(), _>(anyhow::Error::new(Cancelled))
+        }),
+    );
+    assert!(cleaned_up.get());
+    // the cause is reported, not the cancellation it caused
+    assert_eq!(format!("{:#}", both(stopped, failed).unwrap_err()), "404");
+}
+
+#[test]
+fn all_reports_the_cause() {
+    let results = vec![
+        Ok(1),
+        Err(anyhow::Error::new(Cancelled)),
+        Err(anyhow::anyhow!("404")),
+        Err(anyhow::anyhow!("500")),
+    ];
+    assert_eq!(format!("{:#}", all(results).unwrap_err()), "404");
+    assert_eq!(all(vec![Ok(1), Ok(2)]).unwrap(), [1, 2]);
+    assert!(is_cancelled(
+        &all(vec![Ok(1), Err(anyhow::Error::new(Cancelled))]).unwrap_err()
+    ));
+}
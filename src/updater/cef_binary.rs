@@ -1,96 +1,176 @@
+pub mod archives;
+pub mod browser_cache;
+mod bz_parallel;
+pub mod distribution;
+mod extract;
+pub mod format;
+pub mod index;
+pub mod locales;
+mod manifest;
+mod pipe;
+pub mod system;
+
 use std::{
-    io,
-    marker::Unpin,
-    path::{Component, Path, PathBuf},
-    pin::Pin,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, AtomicUsize, Ordering},
-    },
-    time::Duration,
+    env, io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use anyhow::{Context, Error, Result, bail};
-use classicube_helpers::color;
-use futures::{
-    Stream,
-    stream::{StreamExt, TryStreamExt},
-};
+use anyhow::{Context, Result, bail};
+use futures::stream::TryStreamExt;
 use tokio::{
     fs::{self, File},
-    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncWriteExt},
 };
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
-use crate::{async_manager, print_async, status, updater::make_client};
+use self::{
+    archives::ArchiveCache,
+    bz_parallel::ParallelBzDecoder,
+    distribution::Distribution,
+    format::ArchiveFormat,
+    index::CefBuild,
+    locales::Locales,
+    manifest::{Manifest, Verify},
+};
 
-#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-pub const CEF_ARCH: &str = "windows64";
+use crate::updater::{
+    cancel::{self, Cancellable},
+    events::{self, UpdateEvent},
+    human_bytes, make_client, platform, progress, rollback,
+};
 
-#[cfg(all(target_os = "windows", target_arch = "x86"))]
-pub const CEF_ARCH: &str = "windows32";
+pub const CEF_ARCH: &str = platform::CURRENT.cef_arch;
 
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-pub const CEF_ARCH: &str = "linux64";
+pub const CEF_CACHE_PATH: &str = "cef/cache";
 
-#[cfg(all(target_os = "linux", target_arch = "x86"))]
-pub const CEF_ARCH: &str = "linux32";
+pub const CEF_BINARY_PATH: &str = platform::CURRENT.cef_binary_path();
 
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-pub const CEF_ARCH: &str = "linuxarm";
+pub const CEF_BINARY_PATH_NEW: &str = platform::CURRENT.cef_binary_path_new();
 
-#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-pub const CEF_ARCH: &str = "linuxarm64";
+pub const CEF_BINARY_PATH_OLD: &str = platform::CURRENT.cef_binary_path_old();
 
-#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-pub const CEF_ARCH: &str = "macosx64";
+pub const CEF_BINARY_VERSION_PATH: &str = "cef/cef_binary.txt";
 
-#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-pub const CEF_ARCH: &str = "macosarm64";
+pub const CEF_BINARY_DISTRIBUTION_PATH: &str = "cef/cef_binary_distribution.txt";
 
-pub const CEF_CACHE_PATH: &str = "cef/cache";
+pub const CEF_BINARY_LOCALES_PATH: &str = "cef/cef_binary_locales.txt";
 
-#[cfg(not(target_os = "macos"))]
-pub const CEF_BINARY_PATH: &str = "cef/cef_binary";
+pub const CEF_BINARY_MANIFEST_PATH: &str = "cef/cef_binary_manifest.json";
 
-#[cfg(target_os = "macos")]
-pub const CEF_BINARY_PATH: &str = "cef/Chromium Embedded Framework.framework";
+pub const CEF_SYMBOLS_PATH: &str = "cef/cef_binary_symbols";
 
-#[cfg(not(target_os = "macos"))]
-pub const CEF_BINARY_PATH_NEW: &str = "cef/cef_binary-new";
+pub const CEF_SYMBOLS_VERSION_PATH: &str = "cef/cef_binary_symbols.txt";
 
-#[cfg(target_os = "macos")]
-pub const CEF_BINARY_PATH_NEW: &str = "cef/Chromium Embedded Framework.framework-new";
+pub const CEF_LIBRARY_NAME: &str = platform::CURRENT.cef_library_name();
 
-pub const CEF_BINARY_VERSION_PATH: &str = "cef/cef_binary.txt";
+pub const CEF_PRELOAD_LIBRARIES: &[&str] = platform::CURRENT.cef_preload_libraries();
+
+const COMPONENT: &str = "CEF Binary";
 
-async fn get_current_version() -> Option<String> {
-    if let Ok(bytes) = fs::read(CEF_BINARY_VERSION_PATH).await {
+const SYMBOLS_COMPONENT: &str = "CEF Debug Symbols";
+
+fn read_marker(path: &str) -> Option<String> {
+    if let Ok(bytes) = std::fs::read(path) {
         String::from_utf8(bytes).map(|s| s.trim().to_string()).ok()
     } else {
         None
     }
 }
 
-pub async fn update(cef_binary_version: &str) -> Result<bool> {
-    let missing = !Path::new(CEF_BINARY_PATH).is_dir();
+async fn write_marker(path: &str, contents: &str) -> Result<()> {
+    debug!("writing marker {path}");
+    let mut f = File::create(path)
+        .await
+        .with_context(|| format!("create {path}"))?;
+    f.write_all(contents.as_bytes())
+        .await
+        .with_context(|| format!("write {path}"))?;
+    Ok(())
+}
+
+pub fn get_current_version() -> Option<String> {
+    read_marker(CEF_BINARY_VERSION_PATH)
+}
 
-    let needs_update = missing
+/// Installs from before distributions were selectable are minimal.
+pub fn get_current_distribution() -> Distribution {
+    read_marker(CEF_BINARY_DISTRIBUTION_PATH)
+        .and_then(|s| Distribution::parse(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Installs from before locales were selectable have all of them.
+pub fn get_current_locales() -> Locales {
+    read_marker(CEF_BINARY_LOCALES_PATH)
+        .map(|s| Locales::parse(&s))
+        .unwrap_or(Locales::All)
+}
+
+fn binary_needs_update(version: &str, distribution: Distribution, locales: &Locales) -> bool {
+    !Path::new(CEF_BINARY_PATH).is_dir()
         || get_current_version()
-            .await
-            .map(|cur| cur != cef_binary_version)
-            .unwrap_or(true);
-
-    if needs_update {
-        print_async(format!(
-            "{}Updating {}CEF Binary {}to {}{}",
-            color::PINK,
-            color::LIME,
-            color::PINK,
-            color::GREEN,
-            cef_binary_version
-        ))
-        .await;
+            .map(|cur| cur != version)
+            .unwrap_or(true)
+        || get_current_distribution() != distribution
+        // a language that wasn't extracted before needs the archive again
+        || !get_current_locales().contains(locales)
+}
+
+fn symbols_need_update(version: &str) -> bool {
+    distribution::symbols_wanted()
+        && (read_marker(CEF_SYMBOLS_VERSION_PATH).as_deref() != Some(version)
+            || !Path::new(CEF_SYMBOLS_PATH).is_dir())
+}
+
+/// Install CEF `version` in `distribution` unless it's already there. Only
+/// then is `resolve` called to find the build on the CDN (fetching its
+/// multi-MB index), and the build it found is returned.
+pub async fn update<F>(
+    version: &str,
+    distribution: Distribution,
+    resolve: impl FnOnce() -> F,
+    cancel: &CancellationToken,
+) -> Result<Option<CefBuild>>
+where
+    F: Future<Output = Result<CefBuild>>,
+{
+    events::emit(UpdateEvent::Checking {
+        component: COMPONENT.to_string(),
+    });
+
+    let locales = Locales::from_env();
+    if !binary_needs_update(version, distribution, &locales) && !symbols_need_update(version) {
+        events::emit(UpdateEvent::UpToDate {
+            component: COMPONENT.to_string(),
+        });
+        return Ok(None);
+    }
+
+    let build = resolve().await?;
+    let cef_binary_version = build.version.as_str();
+    let missing = !Path::new(CEF_BINARY_PATH).is_dir();
+
+    if binary_needs_update(cef_binary_version, build.distribution, &locales) {
+        events::emit(UpdateEvent::Updating {
+            component: COMPONENT.to_string(),
+            version: cef_binary_version.to_string(),
+        });
+
+        // kept with the binary until the new one has loaded, see `rollback`
+        if !missing {
+            rollback::record_markers(&[
+                CEF_BINARY_VERSION_PATH,
+                CEF_BINARY_DISTRIBUTION_PATH,
+                CEF_BINARY_LOCALES_PATH,
+                CEF_BINARY_MANIFEST_PATH,
+            ])?;
+        }
+
+        // describes the binary we're about to replace
+        remove_manifest().await?;
+        let previous_version = get_current_version();
 
         if Path::new(CEF_BINARY_PATH_NEW).is_dir() {
             debug!("cleaning previous {CEF_BINARY_PATH_NEW}");
@@ -103,264 +183,481 @@ pub async fn update(cef_binary_version: &str) -> Result<bool> {
             .await
             .with_context(|| format!("create_dir_all {CEF_BINARY_PATH_NEW}"))?;
 
-        debug!("starting download + extract for {cef_binary_version}");
-        download(cef_binary_version).await?;
+        debug!(
+            "starting download + extract for {cef_binary_version} ({}, locales {locales})",
+            build.distribution
+        );
+        if let Err(e) = download(&build, &locales, cancel).await {
+            remove_staging().await;
+            return Err(e);
+        }
         debug!("download + extract finished");
 
-        {
-            // mark as updated
-            debug!("writing version marker to {CEF_BINARY_VERSION_PATH}");
-            let mut f = File::create(CEF_BINARY_VERSION_PATH)
-                .await
-                .with_context(|| format!("create {CEF_BINARY_VERSION_PATH}"))?;
-            f.write_all(cef_binary_version.as_bytes())
-                .await
-                .with_context(|| format!("write {CEF_BINARY_VERSION_PATH}"))?;
-        }
+        // mark as updated
+        write_marker(CEF_BINARY_VERSION_PATH, cef_binary_version).await?;
+        write_marker(
+            CEF_BINARY_DISTRIBUTION_PATH,
+            build.distribution.index_type(),
+        )
+        .await?;
+        write_marker(CEF_BINARY_LOCALES_PATH, &locales.to_string()).await?;
+        write_manifest(&build, cancel).await?;
+
+        events::emit(UpdateEvent::Installed {
+            component: COMPONENT.to_string(),
+            version: cef_binary_version.to_string(),
+        });
 
-        print_async(format!(
-            "{}Updated to {}{} {}",
-            color::LIME,
-            color::GREEN,
-            "CEF Binary",
-            cef_binary_version
-        ))
-        .await;
-
-        // remove old cef binary caches
-        if Path::new(CEF_CACHE_PATH).is_dir() {
-            debug!("removing old cache {CEF_CACHE_PATH}");
-            fs::remove_dir_all(CEF_CACHE_PATH)
-                .await
-                .with_context(|| format!("remove_dir_all {CEF_CACHE_PATH}"))?;
+        // same Chromium after a locale or distribution change
+        if previous_version.as_deref() != Some(cef_binary_version) {
+            invalidate_browser_cache().await?;
         }
-
-        Ok(true)
     } else {
-        Ok(false)
+        events::emit(UpdateEvent::UpToDate {
+            component: COMPONENT.to_string(),
+        });
     }
-}
 
-struct FuturesBlockOnReader<R>
-where
-    R: AsyncRead,
-{
-    async_reader: R,
-}
-
-impl<R> io::Read for FuturesBlockOnReader<R>
-where
-    R: AsyncRead + Unpin,
-{
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        futures::executor::block_on(self.async_reader.read(buf))
+    // symbols are only for crash analysis, never worth failing the update over
+    if symbols_need_update(cef_binary_version)
+        && let Err(e) = update_symbols(&build, cancel).await
+    {
+        if cancel::is_cancelled(&e) {
+            return Err(e);
+        }
+        warn!("couldn't install CEF debug symbols: {:#}", e);
+        events::emit(UpdateEvent::Failed {
+            component: SYMBOLS_COMPONENT.to_string(),
+            error: format!("{e:#}"),
+        });
     }
+
+    Ok(Some(build))
 }
 
-async fn download(cef_binary_version: &str) -> Result<()> {
-    let url = format!(
-        "https://cef-builds.spotifycdn.com/cef_binary_{cef_binary_version}_{CEF_ARCH}_minimal.tar.bz2"
-    )
-    .replace('+', "%2B");
+async fn update_symbols(build: &CefBuild, cancel: &CancellationToken) -> Result<()> {
+    let (Some(url), Some(file_name)) = (build.symbols_url(), build.symbols_file_name.clone())
+    else {
+        bail!("CEF {} has no debug symbols for {CEF_ARCH}", build.version);
+    };
 
-    debug!("{}", url);
+    events::emit(UpdateEvent::Updating {
+        component: SYMBOLS_COMPONENT.to_string(),
+        version: build.version.clone(),
+    });
 
-    let running = Arc::new(AtomicBool::new(true));
-    let downloaded = Arc::new(AtomicUsize::new(0usize));
-    let response = make_client().get(&url).send().await?.error_for_status()?;
+    if Path::new(CEF_SYMBOLS_PATH).is_dir() {
+        debug!("removing old {CEF_SYMBOLS_PATH}");
+        fs::remove_dir_all(CEF_SYMBOLS_PATH)
+            .await
+            .with_context(|| format!("remove_dir_all {CEF_SYMBOLS_PATH}"))?;
+    }
+    fs::create_dir_all(CEF_SYMBOLS_PATH)
+        .await
+        .with_context(|| format!("create_dir_all {CEF_SYMBOLS_PATH}"))?;
+
+    // the archive is just the symbol files, keep all of them
+    download_archive(
+        SYMBOLS_COMPONENT,
+        &url,
+        &file_name,
+        CEF_SYMBOLS_PATH,
+        |path| (!path.as_os_str().is_empty()).then(|| path.to_path_buf()),
+        cancel,
+    )
+    .await?;
 
-    let maybe_content_length = response.content_length();
+    write_marker(CEF_SYMBOLS_VERSION_PATH, &build.version).await?;
 
-    {
-        let running = Arc::downgrade(&running);
-        let downloaded = downloaded.clone();
-
-        async_manager::spawn_on_main_thread(async move {
-            while let Some(running) = running.upgrade() {
-                if !running.load(Ordering::SeqCst) {
-                    debug!("status loop no longer running");
-                    break;
-                }
+    events::emit(UpdateEvent::Installed {
+        component: SYMBOLS_COMPONENT.to_string(),
+        version: build.version.clone(),
+    });
 
-                let downloaded = downloaded.load(Ordering::SeqCst);
-
-                let message = if let Some(content_length) = maybe_content_length {
-                    format!(
-                        "{:.2}%",
-                        (downloaded as f32 / content_length as f32) * 100.0
-                    )
-                } else {
-                    format!("{} bytes", downloaded)
-                };
-
-                status(format!(
-                    "{}Downloading ({}{}{})",
-                    color::PINK,
-                    color::LIME,
-                    message,
-                    color::PINK,
-                ));
-
-                async_manager::sleep(Duration::from_secs(1)).await;
-            }
+    Ok(())
+}
 
-            debug!("status loop finished");
-        });
+/// Drop what the previous Chromium left in the browser cache that the new one
+/// can't use, keeping the player's logins (see `browser_cache`).
+async fn invalidate_browser_cache() -> Result<()> {
+    if !Path::new(CEF_CACHE_PATH).is_dir() {
+        return Ok(());
     }
 
-    let stream: Pin<Box<dyn Stream<Item = io::Result<_>> + Send>> = response
-        .bytes_stream()
-        .inspect(move |result| {
-            if let Ok(bytes) = result {
-                let len = bytes.len();
-                downloaded.fetch_add(len, Ordering::SeqCst);
-            }
-        })
-        .map_err(io::Error::other)
-        .boxed();
+    if browser_cache::wipe_wanted() {
+        debug!("removing old cache {CEF_CACHE_PATH}");
+        fs::remove_dir_all(CEF_CACHE_PATH)
+            .await
+            .with_context(|| format!("remove_dir_all {CEF_CACHE_PATH}"))?;
+        return Ok(());
+    }
 
-    let stream = tokio_util::io::StreamReader::new(stream);
+    let removed =
+        tokio::task::spawn_blocking(|| browser_cache::invalidate(Path::new(CEF_CACHE_PATH)))
+            .await?
+            .with_context(|| format!("invalidate {CEF_CACHE_PATH}"))?;
+    debug!("removed {} version bound caches", removed.len());
+    Ok(())
+}
 
-    let stream = tokio::io::BufReader::new(stream);
+/// Best effort, `cleanup` catches anything left on the next start.
+async fn remove_staging() {
+    if Path::new(CEF_BINARY_PATH_NEW).is_dir() {
+        debug!("removing unfinished {CEF_BINARY_PATH_NEW}");
+        if let Err(e) = fs::remove_dir_all(CEF_BINARY_PATH_NEW).await {
+            warn!("couldn't remove {CEF_BINARY_PATH_NEW}: {}", e);
+        }
+    }
+}
 
-    let decoder = async_compression::tokio::bufread::BzDecoder::new(stream);
+async fn remove_manifest() -> Result<()> {
+    match fs::remove_file(CEF_BINARY_MANIFEST_PATH).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("remove_file {CEF_BINARY_MANIFEST_PATH}")),
+    }
+}
 
-    let decoder = tokio::io::BufReader::new(decoder);
+async fn write_manifest(build: &CefBuild, cancel: &CancellationToken) -> Result<()> {
+    let build = build.clone();
+    let cancel = cancel.clone();
+    tokio::task::spawn_blocking(move || {
+        let manifest = Manifest::build(
+            Path::new(CEF_BINARY_PATH),
+            &build.version,
+            &build.url(),
+            &build.file_name,
+            &cancel,
+        )?;
+        debug!("writing manifest of {} files", manifest.files.len());
+        manifest.save(Path::new(CEF_BINARY_MANIFEST_PATH))
+    })
+    .await?
+}
+
+/// Check the installed CEF binary against its manifest (see `VERIFY_ENV`)
+/// and extract any missing or damaged files again, from the cached archive
+/// if there is one. Returns whether anything was repaired.
+pub async fn repair(cancel: &CancellationToken) -> Result<bool> {
+    let verify = Verify::from_env();
+    if verify == Verify::Off || env::var_os(system::SYSTEM_CEF_ENV).is_some() {
+        return Ok(false);
+    }
 
-    let bad_reader = FuturesBlockOnReader {
-        async_reader: decoder,
+    let Some(manifest) = Manifest::load(Path::new(CEF_BINARY_MANIFEST_PATH))? else {
+        debug!("no {CEF_BINARY_MANIFEST_PATH}, can't check {CEF_BINARY_PATH}");
+        return Ok(false);
     };
+    if get_current_version().as_deref() != Some(manifest.version.as_str()) {
+        debug!("{CEF_BINARY_MANIFEST_PATH} is for another version, ignoring it");
+        return Ok(false);
+    }
 
-    tokio::task::spawn_blocking(move || {
-        let mut archive = tar::Archive::new(bad_reader);
+    events::emit(UpdateEvent::Verifying {
+        component: COMPONENT.to_string(),
+    });
+    let manifest = Arc::new(manifest);
+    let broken = {
+        let manifest = manifest.clone();
+        let cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            manifest.check(Path::new(CEF_BINARY_PATH), verify, &cancel)
+        })
+        .await??
+    };
+    if broken.is_empty() {
+        events::emit(UpdateEvent::UpToDate {
+            component: COMPONENT.to_string(),
+        });
+        return Ok(false);
+    }
 
-        let mut cef_binary_name: Option<String> = None;
+    warn!("{} CEF binary files are missing or damaged", broken.len());
+    events::emit(UpdateEvent::Repairing {
+        component: COMPONENT.to_string(),
+        files: broken.len(),
+    });
 
-        for file in archive.entries()? {
-            let mut file = file?;
-            let path = file.path()?.clone();
-            let mut components = path.components();
+    for key in &broken {
+        let path = Path::new(CEF_BINARY_PATH).join(key);
+        match fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("remove_file {path:?}")),
+        }
+    }
 
-            // remove cef_binary_* part
-            let first_component = if let Some(Component::Normal(component)) = components.next() {
-                component.to_str().unwrap().to_string()
-            } else {
-                unreachable!();
-            };
+    let distribution = get_current_distribution();
+    let wanted = Arc::new(broken);
+    download_archive(
+        COMPONENT,
+        &manifest.url,
+        &manifest.file_name,
+        CEF_BINARY_PATH,
+        {
+            let wanted = wanted.clone();
+            move |path| {
+                distribution.destination(path).filter(|relative| {
+                    manifest::key_of(relative).is_some_and(|key| wanted.contains(&key))
+                })
+            }
+        },
+        cancel,
+    )
+    .await?;
 
-            // check we always have the same first directory
-            if let Some(cef_binary_name) = &cef_binary_name {
-                assert!(cef_binary_name == &first_component);
+    let still_broken = {
+        let manifest = manifest.clone();
+        let cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            manifest.check(Path::new(CEF_BINARY_PATH), verify, &cancel)
+        })
+        .await??
+    };
+    if !still_broken.is_empty() {
+        bail!(
+            "{} files are still missing or damaged after extracting them again",
+            still_broken.len()
+        );
+    }
+
+    events::emit(UpdateEvent::Repaired {
+        component: COMPONENT.to_string(),
+        files: wanted.len(),
+    });
+
+    Ok(true)
+}
+
+async fn download(build: &CefBuild, locales: &Locales, cancel: &CancellationToken) -> Result<()> {
+    let distribution = build.distribution;
+    let locales = locales.clone();
+    download_archive(
+        COMPONENT,
+        &build.url(),
+        &build.file_name,
+        CEF_BINARY_PATH_NEW,
+        move |path| {
+            if locales.keeps(path) {
+                distribution.destination(path)
             } else {
-                cef_binary_name = Some(first_component);
+                None
             }
+        },
+        cancel,
+    )
+    .await?;
 
-            let trimmed_path: PathBuf = components
-                .inspect(|part| {
-                    if let Component::Normal(_) = part {
-                    } else {
-                        // don't allow anything but Normal
-                        unreachable!();
-                    }
-                })
-                .collect();
+    events::emit(UpdateEvent::Finalizing {
+        component: COMPONENT.to_string(),
+    });
+    let library_path = Path::new(CEF_BINARY_PATH_NEW).join(CEF_LIBRARY_NAME);
+    if !library_path.is_file() {
+        bail!("archive didn't contain {CEF_LIBRARY_NAME}");
+    }
 
-            let mut trimmed_path_components = trimmed_path.components();
+    if Path::new(CEF_BINARY_PATH).is_dir() {
+        if Path::new(CEF_BINARY_PATH_OLD).is_dir() {
+            debug!("removing stale {CEF_BINARY_PATH_OLD}");
+            fs::remove_dir_all(CEF_BINARY_PATH_OLD)
+                .await
+                .with_context(|| format!("remove_dir_all {CEF_BINARY_PATH_OLD}"))?;
+        }
 
-            if let Some(Component::Normal(first_part)) = trimmed_path_components.next() {
-                if first_part == "README.txt" || first_part == "LICENSE.txt" {
-                    let out_path = Path::new(CEF_BINARY_PATH_NEW).join(first_part);
-                    debug!("{:?} {:?}", path, out_path);
+        // kept until the inner plugin has loaded with the new one
+        rollback::record_replaced(Path::new(CEF_BINARY_PATH), Path::new(CEF_BINARY_PATH_OLD))?;
+        debug!("rename {CEF_BINARY_PATH} -> {CEF_BINARY_PATH_OLD}");
+        fs::rename(CEF_BINARY_PATH, CEF_BINARY_PATH_OLD)
+            .await
+            .with_context(|| format!("rename {CEF_BINARY_PATH} -> {CEF_BINARY_PATH_OLD}"))?;
+    }
 
-                    std::fs::create_dir_all(out_path.parent().unwrap())
-                        .with_context(|| format!("create_dir_all {:?}", out_path.parent()))?;
-                    file.unpack(&out_path)
-                        .with_context(|| format!("unpack {:?}", out_path))?;
-                    continue;
-                }
+    debug!("rename {CEF_BINARY_PATH_NEW} -> {CEF_BINARY_PATH}");
+    fs::rename(CEF_BINARY_PATH_NEW, CEF_BINARY_PATH)
+        .await
+        .with_context(|| format!("rename {CEF_BINARY_PATH_NEW} -> {CEF_BINARY_PATH}"))?;
 
-                // windows/linux extract files to cef/cef_binary/
-                #[cfg(not(target_os = "macos"))]
-                {
-                    if let Some(ext) = trimmed_path.extension()
-                        && ((first_part == "Release"
-                            && (ext == "dll" || ext == "bin" || ext == "so"))
-                            || (first_part == "Resources" && (ext == "pak" || ext == "dat")))
-                    {
-                        let even_more_trimmed: PathBuf = trimmed_path_components.collect();
-                        // icu .dat and .bin files must be next to cef.dll
-                        let out_path = Path::new(CEF_BINARY_PATH_NEW).join(even_more_trimmed);
-                        debug!("{:?} {:?}", path, out_path);
-
-                        std::fs::create_dir_all(out_path.parent().unwrap())
-                            .with_context(|| format!("create_dir_all {:?}", out_path.parent()))?;
-                        file.unpack(&out_path)
-                            .with_context(|| format!("unpack {:?}", out_path))?;
-
-                        if ext == "so" {
-                            debug!("stripping {:?}", out_path);
-                            if let Ok(output) =
-                                std::process::Command::new("strip").arg(&out_path).output()
-                                && !output.status.success()
-                            {
-                                error!(
-                                    "strip {:?}\n--- stdout\n{}\n--- stderr\n{}",
-                                    out_path,
-                                    String::from_utf8_lossy(&output.stdout),
-                                    String::from_utf8_lossy(&output.stderr)
-                                );
-                                bail!("couldn't strip {:?}", out_path);
-                            }
-                        }
-                    }
+    Ok(())
+}
+
+/// Extract the archive `file_name` from `url` into `dest_dir`, keeping the
+/// entries `destination` maps (see `Distribution::destination`). The archive
+/// comes from the local cache if it's there and is added to it otherwise.
+///
+/// The format comes from the file name, or the `Content-Type` if the name
+/// doesn't say (see `ArchiveFormat::detect`).
+async fn download_archive<F>(
+    component: &'static str,
+    url: &str,
+    file_name: &str,
+    dest_dir: &'static str,
+    destination: F,
+    cancel: &CancellationToken,
+) -> Result<()>
+where
+    F: Fn(&Path) -> Option<PathBuf> + Clone + Send + 'static,
+{
+    // a cached archive has nothing but its name to tell its format by
+    let cached_format = ArchiveFormat::from_file_name(file_name);
+    let cache = ArchiveCache::from_env().filter(|_| cached_format.is_some());
+
+    // the cache is plain blocking fs code, kept off the async threads
+    let cached = match &cache {
+        Some(cache) => {
+            let cache = cache.clone();
+            let name = file_name.to_string();
+            tokio::task::spawn_blocking(move || cache.get(&name)).await?
+        }
+        None => None,
+    };
+    if let (Some(path), Some(format)) = (cached, cached_format) {
+        debug!("using cached {:?}", path);
+        let file = File::open(&path)
+            .await
+            .with_context(|| format!("open {path:?}"))?;
+        match unpack_archive(
+            file,
+            format,
+            component,
+            dest_dir,
+            destination.clone(),
+            cancel,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e) if cancel::is_cancelled(&e) => return Err(e),
+            Err(e) => {
+                // the download overwrites whatever this left behind, and
+                // `dest_dir` may be a working install being repaired, so
+                // don't wipe it
+                warn!(
+                    "cached {:?} is unusable, downloading it again: {:#}",
+                    path, e
+                );
+                if let Some(cache) = cache.clone() {
+                    let name = file_name.to_string();
+                    tokio::task::spawn_blocking(move || cache.remove(&name)).await?;
                 }
+            }
+        }
+    }
 
-                // extract "Chromium Embedded Framework.framework" to "cef/Chromium Embedded Framework.framework"
-                #[cfg(target_os = "macos")]
-                {
-                    if first_part == "Release" {
-                        if let Some(Component::Normal(second_part)) = trimmed_path_components.next()
-                        {
-                            if second_part == "Chromium Embedded Framework.framework" {
-                                let even_more_trimmed: PathBuf = trimmed_path_components.collect();
-                                let out_path =
-                                    Path::new(CEF_BINARY_PATH_NEW).join(&even_more_trimmed);
-                                debug!("{:?} {:?}", path, out_path);
-
-                                std::fs::create_dir_all(&out_path.parent().unwrap()).with_context(
-                                    || format!("create_dir_all {:?}", out_path.parent()),
-                                )?;
-                                file.unpack(&out_path)
-                                    .with_context(|| format!("unpack {:?}", out_path))?;
-                            }
-                        }
-                    }
+    debug!("{}", url);
+
+    let slot = tokio::select! {
+        slot = progress::acquire_slot() => slot,
+        () = cancel.cancelled() => return Err(cancel::Cancelled.into()),
+    };
+    let response = make_client().get(url).send().await?.error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let format = ArchiveFormat::detect(file_name, content_type)?;
+    debug!("{file_name} is a {format}");
+    let content_length = response.content_length();
+    let transfer = Arc::new(slot.start(component, content_length));
+
+    let pending = match cache.clone() {
+        Some(cache) if content_length.is_some_and(|len| !cache.fits(len)) => {
+            debug!("not caching {file_name}, it's bigger than the whole cache");
+            None
+        }
+        Some(cache) => {
+            let name = file_name.to_string();
+            tokio::task::spawn_blocking(move || cache.start(&name))
+                .await?
+                .inspect_err(|e| warn!("not caching {file_name}: {e}"))
+                .ok()
+                .map(Arc::new)
+        }
+        None => None,
+    };
+
+    let stream = response.bytes_stream().map_err(io::Error::other).and_then({
+        let transfer = transfer.clone();
+        let pending = pending.clone();
+        move |bytes| {
+            transfer.add(bytes.len());
+            let pending = pending.clone();
+            async move {
+                // one chunk at a time, so they're written in order
+                if let Some(pending) = pending {
+                    let chunk = bytes.clone();
+                    tokio::task::spawn_blocking(move || pending.write(&chunk))
+                        .await
+                        .map_err(io::Error::other)?;
                 }
+                Ok(bytes)
             }
         }
+    });
 
-        Ok::<(), Error>(())
-    })
-    .await??;
+    let stream = tokio_util::io::StreamReader::new(Box::pin(stream));
+    unpack_archive(stream, format, component, dest_dir, destination, cancel).await?;
 
-    if Path::new(CEF_BINARY_PATH).is_dir() {
-        debug!("removing existing {CEF_BINARY_PATH} before rename");
-        fs::remove_dir_all(CEF_BINARY_PATH)
-            .await
-            .with_context(|| format!("remove_dir_all {CEF_BINARY_PATH}"))?;
+    drop(transfer);
+
+    if let (Some(cache), Some(pending)) = (cache, pending.and_then(Arc::into_inner)) {
+        let file_name = file_name.to_string();
+        tokio::task::spawn_blocking(move || {
+            // extracted fine, so the archive is good to keep
+            if let Err(e) = pending.commit() {
+                warn!("couldn't cache {file_name}: {e}");
+            }
+            match cache.evict(&file_name) {
+                Ok(0) => {}
+                Ok(freed) => debug!("evicted {} of cached archives", human_bytes(freed)),
+                Err(e) => warn!("couldn't evict cached archives: {e}"),
+            }
+        })
+        .await?;
     }
 
-    debug!("rename {CEF_BINARY_PATH_NEW} -> {CEF_BINARY_PATH}");
-    fs::rename(CEF_BINARY_PATH_NEW, CEF_BINARY_PATH)
-        .await
-        .with_context(|| format!("rename {CEF_BINARY_PATH_NEW} -> {CEF_BINARY_PATH}"))?;
+    Ok(())
+}
 
-    running.store(false, Ordering::SeqCst);
+async fn unpack_archive<R, F>(
+    reader: R,
+    format: ArchiveFormat,
+    component: &'static str,
+    dest_dir: &'static str,
+    destination: F,
+    cancel: &CancellationToken,
+) -> Result<()>
+where
+    R: AsyncRead + Send + Unpin,
+    F: Fn(&Path) -> Option<PathBuf> + Send + 'static,
+{
+    events::emit(UpdateEvent::Decompressing {
+        component: component.to_string(),
+    });
 
-    async_manager::run_on_main_thread(async {
-        status("");
-    })
-    .await;
+    // decompress on the async side (bzip2 blocks are decoded on every core),
+    // untar on a blocking thread, with a bounded channel in between for
+    // backpressure
+    // a cancel fails the reader, which fails the pipe, which stops the
+    // extractor at its next read
+    let reader = Cancellable::new(reader, cancel.clone());
+    let (sender, reader_side) = pipe::channel();
+    let extractor = tokio::task::spawn_blocking(move || {
+        extract::unpack(reader_side, component, Path::new(dest_dir), destination)
+    });
+    let decompressor = async move {
+        match format {
+            ArchiveFormat::TarBz2 if bz_parallel::worth_it() => {
+                ParallelBzDecoder::new().pump(reader, sender).await
+            }
+            format => sender.pump(format.decoder(reader)).await,
+        }
+    };
+    let (pumped, extracted) = tokio::join!(decompressor, extractor);
+
+    // a download/decompress error also makes the extractor fail, but with a
+    // less useful message, so report it first
+    pumped.context("download")?;
+    extracted??;
 
     Ok(())
 }
@@ -386,11 +683,16 @@ fn test_update() {
     std::fs::create_dir_all("cef").unwrap();
     crate::async_manager::block_on_local(async {
         crate::async_manager::spawn(async {
-            assert!(
-                update("134.3.8+gfe66d80+chromium-134.0.6998.166")
-                    .await
-                    .unwrap()
-            );
+            let version = "134.3.8+gfe66d80+chromium-134.0.6998.166";
+            let build = update(
+                version,
+                Distribution::Minimal,
+                || index::resolve(version, Distribution::Minimal),
+                &tokio_util::sync::CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(build.unwrap().version, version);
         })
         .await
         .unwrap();
@@ -0,0 +1,247 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use filetime::FileTime;
+use tracing::*;
+
+use crate::updater::human_bytes;
+
+/// Directory for downloaded CEF archives, `cef/archives` by default.
+pub const ARCHIVE_CACHE_ENV: &str = "CEF_LOADER_ARCHIVE_CACHE";
+
+/// Size cap of the archive cache in MB; `0` turns caching off.
+pub const ARCHIVE_CACHE_SIZE_ENV: &str = "CEF_LOADER_ARCHIVE_CACHE_SIZE";
+
+const DEFAULT_DIR: &str = "cef/archives";
+
+/// Room for the current archive plus the previous one.
+const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+const PART_SUFFIX: &str = ".part";
+
+/// Downloads in progress are never touched; older ones were interrupted.
+const ABANDONED_PART_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Verified CEF archives kept after extraction so reinstalls, repairs and
+/// locale changes don't need the ~100 MB download again. Least recently used
+/// archives are evicted once the cache grows past `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct ArchiveCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ArchiveCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+        }
+    }
+
+    /// `None` if caching is turned off.
+    pub fn from_env() -> Option<Self> {
+        let dir = env::var_os(ARCHIVE_CACHE_ENV)
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| PathBuf::from(DEFAULT_DIR), PathBuf::from);
+
+        let max_bytes = match env::var(ARCHIVE_CACHE_SIZE_ENV) {
+            Ok(value) => match value.trim().parse::<u64>() {
+                Ok(mb) => mb * 1024 * 1024,
+                Err(e) => {
+                    warn!("ignoring {ARCHIVE_CACHE_SIZE_ENV}={value:?}: {e}");
+                    DEFAULT_MAX_BYTES
+                }
+            },
+            Err(_) => DEFAULT_MAX_BYTES,
+        };
+
+        (max_bytes > 0).then(|| Self::new(dir, max_bytes))
+    }
+
+    /// Whether an archive of `len` bytes can be kept at all.
+    pub fn fits(&self, len: u64) -> bool {
+        len <= self.max_bytes
+    }
+
+    /// Only plain file names from the CDN are cached.
+    fn path_of(&self, file_name: &str) -> Option<PathBuf> {
+        let plain = Path::new(file_name)
+            .file_name()
+            .is_some_and(|name| name == file_name);
+        (plain && !file_name.ends_with(PART_SUFFIX)).then(|| self.dir.join(file_name))
+    }
+
+    /// The cached archive, marked as just used.
+    pub fn get(&self, file_name: &str) -> Option<PathBuf> {
+        let path = self.path_of(file_name)?;
+        if !path.is_file() {
+            return None;
+        }
+
+        if let Err(e) = filetime::set_file_mtime(&path, FileTime::now()) {
+            warn!("couldn't touch {:?}: {}", path, e);
+        }
+        Some(path)
+    }
+
+    pub fn remove(&self, file_name: &str) {
+        if let Some(path) = self.path_of(file_name)
+            && let Err(e) = fs::remove_file(&path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("couldn't remove {:?}: {}", path, e);
+        }
+    }
+
+    /// Start writing `file_name`; it only appears in the cache once
+    /// `PendingArchive::commit` is called.
+    pub fn start(&self, file_name: &str) -> io::Result<PendingArchive> {
+        let path = self
+            .path_of(file_name)
+            .ok_or_else(|| io::Error::other(format!("can't cache {file_name:?}")))?;
+        fs::create_dir_all(&self.dir)?;
+
+        let mut part_name = file_name.to_string();
+        part_name.push_str(PART_SUFFIX);
+        let part_path = self.dir.join(part_name);
+        let file = File::create(&part_path)?;
+
+        Ok(PendingArchive {
+            writer: Mutex::new(Some(BufWriter::new(file))),
+            part_path,
+            path,
+            max_bytes: self.max_bytes,
+            committed: false,
+        })
+    }
+
+    /// Remove least recently used archives until the cache fits, never
+    /// removing `keep`. Returns the number of bytes freed.
+    pub fn evict(&self, keep: &str) -> io::Result<u64> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let now = SystemTime::now();
+        let mut freed = 0;
+        let mut archives = Vec::new();
+        for entry in entries {
+            // one unreadable file mustn't keep the rest of the cache growing
+            let (entry, metadata, modified) = match entry.and_then(|entry| {
+                let metadata = entry.metadata()?;
+                let modified = metadata.modified()?;
+                Ok((entry, metadata, modified))
+            }) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("skipping archive cache entry in {:?}: {}", self.dir, e);
+                    continue;
+                }
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+
+            if name.to_string_lossy().ends_with(PART_SUFFIX) {
+                let age = now.duration_since(modified).unwrap_or_default();
+                if age > ABANDONED_PART_AGE && fs::remove_file(entry.path()).is_ok() {
+                    debug!("removed abandoned {:?}", entry.path());
+                    freed += metadata.len();
+                }
+                continue;
+            }
+
+            archives.push((modified, name, entry.path(), metadata.len()));
+        }
+
+        let mut total: u64 = archives.iter().map(|(.., len)| len).sum();
+        archives.sort_by_key(|(modified, ..)| *modified);
+        for (_, name, path, len) in archives {
+            if total <= self.max_bytes {
+                break;
+            }
+            if name == keep {
+                continue;
+            }
+
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("couldn't evict {:?}: {}", path, e);
+                continue;
+            }
+            debug!("evicted {:?} ({})", path, human_bytes(len));
+            total -= len;
+            freed += len;
+        }
+
+        Ok(freed)
+    }
+}
+
+/// An archive being written into the cache as it downloads.
+pub struct PendingArchive {
+    writer: Mutex<Option<BufWriter<File>>>,
+    part_path: PathBuf,
+    path: PathBuf,
+    max_bytes: u64,
+    committed: bool,
+}
+
+impl PendingArchive {
+    /// A failed write only means the archive won't be cached.
+    pub fn write(&self, bytes: &[u8]) {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(file) = writer.as_mut()
+            && let Err(e) = file.write_all(bytes)
+        {
+            warn!("not caching {:?}: {}", self.path, e);
+            *writer = None;
+        }
+    }
+
+    /// Call once the archive has been extracted successfully.
+    pub fn commit(mut self) -> io::Result<()> {
+        let Some(writer) = self.writer.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()?;
+
+        let len = file.metadata()?.len();
+        drop(file);
+        if len > self.max_bytes {
+            debug!(
+                "{:?} ({}) is bigger than the whole cache, not keeping it",
+                self.path,
+                human_bytes(len)
+            );
+            return Ok(());
+        }
+
+        fs::rename(&self.part_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingArchive {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.part_path);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use super::*;
+use crate::test_util::scratch_dir;
+
+fn set_age(path: &Path, seconds_ago: i64) {
+    let now = FileTime::now().unix_seconds();
+    filetime::set_file_mtime(path, FileTime::from_unix_time(now - seconds_ago, 0)).unwrap();
+}
+
+fn add(cache: &ArchiveCache, name: &str, bytes: &[u8]) {
+    let pending = cache.start(name).unwrap();
+    pending.write(bytes);
+    pending.commit().unwrap();
+}
+
+#[test]
+fn commit_makes_archive_available() {
+    let dir = scratch_dir("commit");
+    let cache = ArchiveCache::new(&dir, 1024);
+
+    let pending = cache.start("a.tar.bz2").unwrap();
+    pending.write(b"hello ");
+    pending.write(b"world");
+    assert!(cache.get("a.tar.bz2").is_none());
+    assert!(dir.join("a.tar.bz2.part").is_file());
+
+    pending.commit().unwrap();
+    let path = cache.get("a.tar.bz2").unwrap();
+    assert_eq!(fs::read(path).unwrap(), b"hello world");
+    assert!(!dir.join("a.tar.bz2.part").exists());
+
+    cache.remove("a.tar.bz2");
+    assert!(cache.get("a.tar.bz2").is_none());
+}
+
+#[test]
+fn abandoned_and_oversized_archives_are_not_kept() {
+    let dir = scratch_dir("abandoned");
+    let cache = ArchiveCache::new(&dir, 4);
+
+    let pending = cache.start("a.tar.bz2").unwrap();
+    pending.write(b"partial");
+    drop(pending);
+    assert!(!dir.join("a.tar.bz2.part").exists());
+    assert!(cache.get("a.tar.bz2").is_none());
+
+    // a known size is turned away before downloading, anything else when
+    // it's committed
+    assert!(cache.fits(4));
+    assert!(!cache.fits(5));
+    add(&cache, "a.tar.bz2", b"too big");
+    assert!(cache.get("a.tar.bz2").is_none());
+    assert!(!dir.join("a.tar.bz2.part").exists());
+}
+
+#[test]
+fn only_plain_file_names() {
+    let dir = scratch_dir("names");
+    let cache = ArchiveCache::new(&dir, 1024);
+
+    assert!(cache.start("../escape.tar.bz2").is_err());
+    assert!(cache.start("/tmp/escape.tar.bz2").is_err());
+    assert!(cache.start("x.part").is_err());
+    assert!(cache.get("../escape.tar.bz2").is_none());
+}
+
+#[test]
+fn evicts_least_recently_used() {
+    let dir = scratch_dir("evict");
+    let cache = ArchiveCache::new(&dir, 10);
+
+    add(&cache, "old.tar.bz2", b"1234");
+    add(&cache, "used.tar.bz2", b"1234");
+    add(&cache, "new.tar.bz2", b"1234");
+    set_age(&dir.join("old.tar.bz2"), 300);
+    set_age(&dir.join("used.tar.bz2"), 200);
+    set_age(&dir.join("new.tar.bz2"), 100);
+    // reading it makes it the most recently used
+    cache.get("used.tar.bz2").unwrap();
+
+    // a download in progress and an abandoned one
+    fs::write(dir.join("current.tar.bz2.part"), b"12345678").unwrap();
+    fs::write(dir.join("crashed.tar.bz2.part"), b"12345678").unwrap();
+    set_age(&dir.join("crashed.tar.bz2.part"), 2 * 60 * 60);
+
+    assert_eq!(cache.evict("new.tar.bz2").unwrap(), 8 + 4);
+
+    assert!(cache.get("old.tar.bz2").is_none());
+    assert!(cache.get("used.tar.bz2").is_some());
+    assert!(cache.get("new.tar.bz2").is_some());
+    assert!(dir.join("current.tar.bz2.part").exists());
+    assert!(!dir.join("crashed.tar.bz2.part").exists());
+
+    // `keep` survives even when it alone is over the cap
+    let cache = ArchiveCache::new(&dir, 2);
+    assert_eq!(cache.evict("new.tar.bz2").unwrap(), 4);
+    assert!(cache.get("new.tar.bz2").is_some());
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use tracing::*;
+
+use crate::updater::env_flag;
+
+/// Set to `1` or `true` to delete the whole browser cache (cookies, local
+/// storage and all) when CEF changes version, instead of only what the new
+/// Chromium can't use.
+pub const WIPE_CACHE_ENV: &str = "CEF_LOADER_WIPE_CACHE";
+
+/// Directories Chromium fills with data tied to its exact version: compiled
+/// shaders and V8 bytecode. A new build ignores or, worse, trips over them,
+/// and they're rebuilt on demand. Cookies, local storage, IndexedDB and the
+/// HTTP cache are kept: Chromium migrates those itself.
+const VERSION_BOUND_DIRS: &[&str] = &[
+    "Code Cache",
+    "DawnCache",
+    "DawnGraphiteCache",
+    "DawnWebGPUCache",
+    "GPUCache",
+    "GrShaderCache",
+    "GraphiteDawnCache",
+    "ShaderCache",
+];
+
+/// How deep below the cache root to look: the root itself holds the shared
+/// GPU caches, profiles (`Default`, or one per request context) sit one or
+/// two levels down.
+const MAX_DEPTH: usize = 3;
+
+pub fn wipe_wanted() -> bool {
+    env_flag(WIPE_CACHE_ENV)
+}
+
+/// Remove everything under `root` that a different Chromium version can't
+/// reuse. Returns what was removed.
+pub fn invalidate(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    invalidate_dir(root, 0, &mut removed)?;
+    Ok(removed)
+}
+
+fn invalidate_dir(dir: &Path, depth: usize, removed: &mut Vec<PathBuf>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        // never follows symlinks
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        if VERSION_BOUND_DIRS
+            .iter()
+            .any(|name| entry.file_name() == *name)
+        {
+            debug!("removing {:?}", path);
+            fs::remove_dir_all(&path)?;
+            removed.push(path);
+        } else if depth < MAX_DEPTH {
+            invalidate_dir(&path, depth + 1, removed)?;
+        }
+    }
+
+    Ok(())
+}
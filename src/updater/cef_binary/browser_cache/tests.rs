@@ -0,0 +1,65 @@
+use super::*;
+use crate::test_util::scratch_dir;
+
+fn touch(path: &Path) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, b"x").unwrap();
+}
+
+#[test]
+fn keeps_cookies_and_storage() {
+    let root = scratch_dir("invalidate");
+    for kept in [
+        "Local State",
+        "Default/Cookies",
+        "Default/Local Storage/leveldb/000003.log",
+        "Default/IndexedDB/https_example.com_0.indexeddb.leveldb/LOG",
+        "Default/Cache/Cache_Data/index",
+    ] {
+        touch(&root.join(kept));
+    }
+    for stale in [
+        "GrShaderCache/data_0",
+        "ShaderCache/data_0",
+        "Default/GPUCache/index",
+        "Default/Code Cache/js/index",
+        "contexts/1/DawnWebGPUCache/data_0",
+    ] {
+        touch(&root.join(stale));
+    }
+    // a file that merely has a cache's name isn't touched
+    touch(&root.join("Default/GPUCache.txt"));
+
+    let mut removed = invalidate(&root).unwrap();
+    removed.sort();
+    let mut expected: Vec<_> = [
+        "GrShaderCache",
+        "ShaderCache",
+        "Default/GPUCache",
+        "Default/Code Cache",
+        "contexts/1/DawnWebGPUCache",
+    ]
+    .iter()
+    .map(|path| root.join(path))
+    .collect();
+    expected.sort();
+    assert_eq!(removed, expected);
+
+    for path in &expected {
+        assert!(!path.exists(), "{path:?}");
+    }
+    assert!(root.join("Local State").is_file());
+    assert!(root.join("Default/Cookies").is_file());
+    assert!(
+        root.join("Default/Local Storage/leveldb/000003.log")
+            .is_file()
+    );
+    assert!(root.join("Default/Cache/Cache_Data/index").is_file());
+    assert!(root.join("Default/GPUCache.txt").is_file());
+}
+
+#[test]
+fn missing_cache_is_fine() {
+    let root = scratch_dir("missing").join("cache");
+    assert!(invalidate(&root).unwrap().is_empty());
+}
@@ -0,0 +1,420 @@
+#[cfg(test)]
+mod tests;
+
+use std::{collections::VecDeque, io, io::Read, sync::Arc, thread};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    task::JoinHandle,
+};
+use tracing::*;
+
+use super::pipe;
+
+/// Start of every compressed block (BCD pi), not byte aligned.
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+
+/// End of stream marker (BCD sqrt(pi)), followed by the 32 bit combined CRC.
+const EOS_MAGIC: u64 = 0x1772_4538_5090;
+
+const MAGIC_BITS: u64 = 48;
+const MAGIC_MASK: u64 = (1 << MAGIC_BITS) - 1;
+/// Enough history for a magic ending anywhere in the next byte.
+const WINDOW_MASK: u64 = (1 << (MAGIC_BITS + 8)) - 1;
+const CRC_BITS: u64 = 32;
+
+const READ_SIZE: usize = 64 * 1024;
+
+/// A false boundary (the 48 bit magic occurring by chance inside compressed
+/// data) makes a block fail to decode; it's retried glued to the following
+/// blocks, up to this many times.
+const MAX_MERGES: usize = 3;
+
+/// Bits copied out of the compressed stream, starting byte aligned.
+#[derive(Debug, Default, Clone)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: u64,
+}
+
+impl Bits {
+    fn push_bits(&mut self, value: u64, count: u64) {
+        for i in (0..count).rev() {
+            self.push_bit(((value >> i) & 1) as u8);
+        }
+    }
+
+    fn push_bit(&mut self, bit: u8) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit != 0 {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Append bits `start..end` of `src`.
+    fn extend_from(&mut self, src: &[u8], start: u64, end: u64) {
+        let mut pos = start;
+
+        // bit by bit until our end is byte aligned, then a byte at a time
+        while pos < end && !self.len.is_multiple_of(8) {
+            self.push_bit(bit_at(src, pos));
+            pos += 1;
+        }
+
+        let shift = (pos % 8) as u32;
+        while end - pos >= 8 {
+            let i = (pos / 8) as usize;
+            let byte = if shift == 0 {
+                src[i]
+            } else {
+                (src[i] << shift) | (src[i + 1] >> (8 - shift))
+            };
+            self.bytes.push(byte);
+            self.len += 8;
+            pos += 8;
+        }
+
+        while pos < end {
+            self.push_bit(bit_at(src, pos));
+            pos += 1;
+        }
+    }
+
+    fn append(&mut self, other: &Bits) {
+        self.extend_from(&other.bytes, 0, other.len);
+    }
+
+    fn read(&self, start: u64, count: u64) -> u64 {
+        (start..start + count).fold(0, |acc, pos| {
+            (acc << 1) | u64::from(bit_at(&self.bytes, pos))
+        })
+    }
+}
+
+fn bit_at(bytes: &[u8], pos: u64) -> u8 {
+    (bytes[(pos / 8) as usize] >> (7 - pos % 8)) & 1
+}
+
+/// One or more consecutive compressed blocks, starting at a block magic.
+struct Segment {
+    level: u8,
+    bits: Arc<Bits>,
+}
+
+impl Segment {
+    /// Wrap the blocks in a stream header and trailer so a stock decoder
+    /// accepts them on their own. The combined CRC of a single-block stream
+    /// is just that block's CRC, which sits right after its magic.
+    fn to_stream(&self) -> Vec<u8> {
+        let mut out = Bits::default();
+        for byte in [b'B', b'Z', b'h', b'0' + self.level] {
+            out.push_bits(u64::from(byte), 8);
+        }
+        out.append(&self.bits);
+        out.push_bits(EOS_MAGIC, MAGIC_BITS);
+        out.push_bits(self.bits.read(MAGIC_BITS, CRC_BITS), CRC_BITS);
+        out.bytes
+    }
+
+    fn decode(&self) -> io::Result<Vec<u8>> {
+        let stream = self.to_stream();
+        let mut out = Vec::new();
+        bzip2::read::BzDecoder::new(stream.as_slice()).read_to_end(&mut out)?;
+        Ok(out)
+    }
+}
+
+enum State {
+    /// Expecting `BZh1`..`BZh9` at this absolute byte offset.
+    Header { at: u64 },
+    /// Inside a stream, the current segment starting at bit `start`.
+    Blocks { level: u8, start: u64 },
+    /// Waiting for the combined CRC after an end of stream marker.
+    Trailer { end: u64 },
+}
+
+/// Splits a (possibly multi-stream) bzip2 byte stream into independently
+/// decodable block segments as data arrives.
+struct Splitter {
+    /// Compressed data from absolute byte `base` onwards.
+    buf: Vec<u8>,
+    base: u64,
+    state: State,
+    /// Rolling window of the last (up to 56) bits before `scan`, of which
+    /// `window_bits` were scanned since the last reset.
+    window: u64,
+    window_bits: u64,
+    scan: u64,
+}
+
+impl Splitter {
+    fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            base: 0,
+            state: State::Header { at: 0 },
+            window: 0,
+            window_bits: 0,
+            scan: 0,
+        }
+    }
+
+    fn end_bit(&self) -> u64 {
+        (self.base + self.buf.len() as u64) * 8
+    }
+
+    fn bit(&self, pos: u64) -> u8 {
+        bit_at(&self.buf, pos - self.base * 8)
+    }
+
+    fn take_segment(&mut self, level: u8, start: u64, end: u64) -> Segment {
+        let mut bits = Bits::default();
+        bits.extend_from(&self.buf, start - self.base * 8, end - self.base * 8);
+
+        // nothing before `end` is needed anymore
+        let drop_bytes = end / 8 - self.base;
+        self.buf.drain(..drop_bytes as usize);
+        self.base += drop_bytes;
+
+        Segment {
+            level,
+            bits: Arc::new(bits),
+        }
+    }
+
+    fn reset_scan(&mut self, from: u64) {
+        self.scan = from;
+        self.window = 0;
+        self.window_bits = 0;
+    }
+
+    /// Advance `scan` to just past the next block or end of stream magic,
+    /// returning where it starts and which one it was.
+    fn scan_for_magic(&mut self) -> Option<(u64, u64)> {
+        let end_bit = self.end_bit();
+        while self.scan < end_bit {
+            let whole_byte = self.scan.is_multiple_of(8) && end_bit - self.scan >= 8;
+            let (window, new_bits) = if whole_byte {
+                let byte = self.buf[(self.scan / 8 - self.base) as usize];
+                ((self.window << 8) | u64::from(byte), 8)
+            } else {
+                ((self.window << 1) | u64::from(self.bit(self.scan)), 1)
+            };
+
+            // check every 48 bit window ending inside the bits just added
+            for k in (0..new_bits).rev() {
+                let candidate = (window >> k) & MAGIC_MASK;
+                let bits = self.window_bits + new_bits - k;
+                if bits >= MAGIC_BITS && (candidate == BLOCK_MAGIC || candidate == EOS_MAGIC) {
+                    self.scan += new_bits - k;
+                    return Some((self.scan - MAGIC_BITS, candidate));
+                }
+            }
+
+            self.window = window & WINDOW_MASK;
+            self.window_bits += new_bits;
+            self.scan += new_bits;
+        }
+
+        None
+    }
+
+    /// Feed more compressed bytes, returning every segment completed by them.
+    fn push(&mut self, data: &[u8]) -> io::Result<Vec<Segment>> {
+        self.buf.extend_from_slice(data);
+
+        let mut segments = Vec::new();
+        loop {
+            match self.state {
+                State::Header { at } => {
+                    let offset = (at - self.base) as usize;
+                    let Some(header) = self.buf.get(offset..offset + 4) else {
+                        break;
+                    };
+                    if &header[..3] != b"BZh" || !(b'1'..=b'9').contains(&header[3]) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "not a bzip2 stream",
+                        ));
+                    }
+                    let level = header[3] - b'0';
+                    let start = (at + 4) * 8;
+                    self.state = State::Blocks { level, start };
+                    self.reset_scan(start);
+                }
+
+                State::Blocks { level, start } => {
+                    let Some((magic_start, magic)) = self.scan_for_magic() else {
+                        break;
+                    };
+
+                    if magic_start > start {
+                        segments.push(self.take_segment(level, start, magic_start));
+                    }
+
+                    if magic == BLOCK_MAGIC {
+                        self.state = State::Blocks {
+                            level,
+                            start: magic_start,
+                        };
+                        self.reset_scan(magic_start + MAGIC_BITS);
+                    } else {
+                        self.state = State::Trailer {
+                            end: magic_start + MAGIC_BITS + CRC_BITS,
+                        };
+                    }
+                }
+
+                State::Trailer { end } => {
+                    if self.end_bit() < end {
+                        break;
+                    }
+                    // streams are padded to a whole byte
+                    let next = end.div_ceil(8);
+                    let drop_bytes = (next - self.base).min(self.buf.len() as u64);
+                    self.buf.drain(..drop_bytes as usize);
+                    self.base += drop_bytes;
+                    self.state = State::Header { at: next };
+                }
+            }
+        }
+
+        Ok(segments)
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        match self.state {
+            State::Header { at } if at == self.base && self.buf.is_empty() => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated bzip2 stream",
+            )),
+        }
+    }
+}
+
+fn threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Splitting and re-wrapping blocks only pays off with more than one core.
+pub fn worth_it() -> bool {
+    threads() > 1
+}
+
+struct Job {
+    segment: Segment,
+    handle: JoinHandle<io::Result<Vec<u8>>>,
+}
+
+/// Decompresses `reader` using every core: blocks are split off as they
+/// download and decoded in parallel, then sent to `sender` in order.
+pub struct ParallelBzDecoder {
+    jobs: VecDeque<Job>,
+    max_in_flight: usize,
+}
+
+impl ParallelBzDecoder {
+    pub fn new() -> Self {
+        Self {
+            jobs: VecDeque::new(),
+            max_in_flight: threads() * 2,
+        }
+    }
+
+    /// Like `pipe::Sender::pump`: read errors are forwarded and returned, and
+    /// a consumer that went away stops us early with `Ok`.
+    pub async fn pump<R: AsyncRead + Unpin>(
+        mut self,
+        mut reader: R,
+        sender: pipe::Sender,
+    ) -> io::Result<()> {
+        match self.run(&mut reader, &sender).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                sender.fail(io::Error::new(e.kind(), e.to_string())).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn run<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+        sender: &pipe::Sender,
+    ) -> io::Result<()> {
+        let mut splitter = Splitter::new();
+        let mut chunk = vec![0; READ_SIZE];
+
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+
+            for segment in splitter.push(&chunk[..n])? {
+                self.spawn(segment);
+                while self.jobs.len() > self.max_in_flight {
+                    if !self.send_next(sender).await? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        splitter.finish()?;
+
+        while !self.jobs.is_empty() {
+            if !self.send_next(sender).await? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn(&mut self, segment: Segment) {
+        let task_segment = Segment {
+            level: segment.level,
+            bits: segment.bits.clone(),
+        };
+        let handle = tokio::task::spawn_blocking(move || task_segment.decode());
+        self.jobs.push_back(Job { segment, handle });
+    }
+
+    /// Wait for the oldest block and send it on. `Ok(false)` if the consumer
+    /// went away.
+    async fn send_next(&mut self, sender: &pipe::Sender) -> io::Result<bool> {
+        let job = self.jobs.pop_front().expect("send_next with no jobs");
+        let mut result = job.handle.await.map_err(io::Error::other)?;
+
+        let mut merged = job.segment;
+        let mut merges = 0;
+        while let Err(e) = &result {
+            let Some(next) = self.jobs.pop_front().filter(|_| merges < MAX_MERGES) else {
+                return Err(io::Error::new(e.kind(), e.to_string()));
+            };
+            debug!("bzip2 block failed to decode ({e}), retrying merged with the next one");
+            next.handle.abort();
+
+            let mut bits = (*merged.bits).clone();
+            bits.append(&next.segment.bits);
+            merged = Segment {
+                level: merged.level,
+                bits: Arc::new(bits),
+            };
+            let retry = Segment {
+                level: merged.level,
+                bits: merged.bits.clone(),
+            };
+            result = tokio::task::spawn_blocking(move || retry.decode())
+                .await
+                .map_err(io::Error::other)?;
+            merges += 1;
+        }
+
+        Ok(sender.send(result?).await)
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::{Read, Write};
+
+use bzip2::{Compression, write::BzEncoder};
+
+use super::*;
+
+/// Text-ish data that doesn't compress to nothing, `len` bytes.
+fn sample(len: usize, seed: u32) -> Vec<u8> {
+    const WORDS: &[&str] = &[
+        "cef",
+        "loader",
+        "chromium",
+        "classicube",
+        "block",
+        "pak",
+        "\n",
+    ];
+    let mut state = seed;
+    let mut out = Vec::with_capacity(len + 16);
+    while out.len() < len {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let word = WORDS[(state >> 16) as usize % WORDS.len()];
+        out.extend_from_slice(word.as_bytes());
+        out.extend_from_slice(format!("{} ", state % 1000).as_bytes());
+    }
+    out.truncate(len);
+    out
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    // level 1 = 100k blocks, so a few hundred KB gives several blocks
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::new(1));
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn split_all(compressed: &[u8], chunk_size: usize) -> io::Result<Vec<Segment>> {
+    let mut splitter = Splitter::new();
+    let mut segments = Vec::new();
+    for chunk in compressed.chunks(chunk_size) {
+        segments.extend(splitter.push(chunk)?);
+    }
+    splitter.finish()?;
+    Ok(segments)
+}
+
+fn decode_all(segments: &[Segment]) -> Vec<u8> {
+    segments
+        .iter()
+        .flat_map(|segment| segment.decode().unwrap())
+        .collect()
+}
+
+#[test]
+fn bits_extend_unaligned() {
+    let src = [0b1010_1100, 0b0101_0011, 0b1111_0000];
+    let mut bits = Bits::default();
+    bits.push_bits(0b1, 1);
+    bits.extend_from(&src, 3, 21);
+    assert_eq!(bits.len, 19);
+    assert_eq!(bits.read(0, 19), 0b101_1000_1010_0111_1110);
+}
+
+#[test]
+fn splits_into_independent_blocks() {
+    let data = sample(450_000, 1);
+    let compressed = compress(&data);
+
+    for chunk_size in [1, 7, 4096, compressed.len()] {
+        let segments = split_all(&compressed, chunk_size).unwrap();
+        assert!(segments.len() >= 4, "only {} segments", segments.len());
+        assert_eq!(decode_all(&segments), data);
+    }
+}
+
+#[test]
+fn handles_concatenated_streams() {
+    let first = sample(150_000, 2);
+    let second = sample(120_000, 3);
+    let mut compressed = compress(&first);
+    compressed.extend(compress(&second));
+    compressed.extend(compress(b""));
+
+    let segments = split_all(&compressed, 1000).unwrap();
+    assert_eq!(decode_all(&segments), [first, second].concat());
+}
+
+#[test]
+fn rejects_truncated_and_foreign_data() {
+    let compressed = compress(&sample(50_000, 4));
+    assert!(split_all(&compressed[..compressed.len() - 3], 512).is_err());
+    assert!(split_all(b"PK\x03\x04 not bzip2", 512).is_err());
+}
+
+#[tokio::test]
+async fn false_boundary_is_merged_with_next_block() {
+    let data = sample(80_000, 5);
+    let compressed = compress(&data);
+    let segments = split_all(&compressed, compressed.len()).unwrap();
+    let [segment] = segments.as_slice() else {
+        panic!("expected a single block");
+    };
+
+    // pretend the magic was seen in the middle of the block
+    let middle = segment.bits.len / 2;
+    let mut head = Bits::default();
+    head.extend_from(&segment.bits.bytes, 0, middle);
+    let mut tail = Bits::default();
+    tail.extend_from(&segment.bits.bytes, middle, segment.bits.len);
+
+    let mut decoder = ParallelBzDecoder::new();
+    for bits in [head, tail] {
+        decoder.spawn(Segment {
+            level: segment.level,
+            bits: Arc::new(bits),
+        });
+    }
+
+    let (sender, mut reader) = pipe::channel();
+    let consumer = tokio::task::spawn_blocking(move || {
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).map(|_| out)
+    });
+    assert!(decoder.send_next(&sender).await.unwrap());
+    assert!(decoder.jobs.is_empty());
+    drop(sender);
+
+    assert_eq!(consumer.await.unwrap().unwrap(), data);
+}
+
+#[tokio::test]
+async fn pump_matches_serial_decoder() {
+    let data = sample(600_000, 6);
+    let compressed = compress(&data);
+
+    let (sender, mut reader) = pipe::channel();
+    let consumer = tokio::task::spawn_blocking(move || {
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).map(|_| out)
+    });
+    ParallelBzDecoder::new()
+        .pump(compressed.as_slice(), sender)
+        .await
+        .unwrap();
+
+    assert_eq!(consumer.await.unwrap().unwrap(), data);
+}
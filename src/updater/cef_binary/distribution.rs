@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env, fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use tracing::*;
+
+use crate::updater::{
+    env_flag,
+    platform::{self, Os},
+};
+
+/// Which CEF distribution to install: `minimal` (the default) or `standard`.
+pub const DISTRIBUTION_ENV: &str = "CEF_LOADER_DISTRIBUTION";
+
+/// Set to `1` to also download the release debug symbols matching the
+/// installed CEF binary, for crash analysis.
+pub const SYMBOLS_ENV: &str = "CEF_LOADER_SYMBOLS";
+
+/// Where the files only the `standard` distribution ships (headers, the
+/// wrapper library sources, cmake files) end up, inside the binary directory.
+pub const SDK_DIR_NAME: &str = "sdk";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Distribution {
+    /// Just what's needed at runtime, for players.
+    #[default]
+    Minimal,
+    /// Runtime files plus everything needed to build against CEF, for plugin
+    /// developers.
+    Standard,
+}
+
+impl Distribution {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "minimal" => Ok(Self::Minimal),
+            "standard" => Ok(Self::Standard),
+            other => bail!("unknown CEF distribution {other:?}, expected minimal or standard"),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        match env::var(DISTRIBUTION_ENV) {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|e| {
+                warn!("ignoring {DISTRIBUTION_ENV}: {:#}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// The file `type` in the CDN's `index.json`, also the archive suffix.
+    pub fn index_type(self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Standard => "standard",
+        }
+    }
+
+    /// Where an archive entry goes, relative to the binary directory, or
+    /// `None` to skip it. `path` has the `cef_binary_*` directory removed.
+    pub fn destination(self, path: &Path) -> Option<PathBuf> {
+        runtime_destination(platform::CURRENT.os, path).or_else(|| match self {
+            Self::Minimal => None,
+            Self::Standard => sdk_destination(path),
+        })
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.index_type())
+    }
+}
+
+pub fn symbols_wanted() -> bool {
+    env_flag(SYMBOLS_ENV)
+}
+
+/// Runtime files, laid out the way `os` loads them.
+fn runtime_destination(os: Os, path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let Some(Component::Normal(first_part)) = components.next() else {
+        return None;
+    };
+
+    if first_part == "README.txt" || first_part == "LICENSE.txt" {
+        return Some(PathBuf::from(first_part));
+    }
+
+    match os {
+        // windows/linux extract files to cef/cef_binary/
+        Os::Windows | Os::Linux => {
+            let ext = path.extension()?;
+            if (first_part == "Release" && (ext == "dll" || ext == "bin" || ext == "so"))
+                || (first_part == "Resources" && (ext == "pak" || ext == "dat"))
+            {
+                // icu .dat and .bin files must be next to cef.dll
+                return Some(components.collect());
+            }
+        }
+
+        // extract "Chromium Embedded Framework.framework" to "cef/Chromium Embedded Framework.framework"
+        Os::Mac => {
+            if first_part == "Release"
+                && let Some(Component::Normal(second_part)) = components.next()
+                && second_part == "Chromium Embedded Framework.framework"
+            {
+                return Some(components.collect());
+            }
+        }
+    }
+
+    None
+}
+
+/// Developer files, keeping the archive layout under `SDK_DIR_NAME`.
+fn sdk_destination(path: &Path) -> Option<PathBuf> {
+    let Some(Component::Normal(first_part)) = path.components().next() else {
+        return None;
+    };
+
+    let wanted = first_part == "include"
+        || first_part == "libcef_dll"
+        || first_part == "cmake"
+        || first_part == "CMakeLists.txt"
+        // import library for linking against libcef.dll
+        || (first_part == "Release" && path.extension().is_some_and(|ext| ext == "lib"));
+
+    wanted.then(|| Path::new(SDK_DIR_NAME).join(path))
+}
@@ -0,0 +1,95 @@
+use super::*;
+
+#[test]
+fn parse() {
+    assert_eq!(
+        Distribution::parse("minimal").unwrap(),
+        Distribution::Minimal
+    );
+    assert_eq!(
+        Distribution::parse(" Standard\n").unwrap(),
+        Distribution::Standard
+    );
+    assert!(Distribution::parse("client").is_err());
+    assert_eq!(Distribution::Standard.to_string(), "standard");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn allowlist_per_distribution() {
+    let dest = |distribution: Distribution, path: &str| {
+        distribution
+            .destination(Path::new(path))
+            .map(|p| p.to_string_lossy().into_owned())
+    };
+
+    for distribution in [Distribution::Minimal, Distribution::Standard] {
+        assert_eq!(
+            dest(distribution, "Release/libcef.so").as_deref(),
+            Some("libcef.so")
+        );
+        assert_eq!(
+            dest(distribution, "Resources/locales/en-US.pak").as_deref(),
+            Some("locales/en-US.pak")
+        );
+        assert_eq!(
+            dest(distribution, "LICENSE.txt").as_deref(),
+            Some("LICENSE.txt")
+        );
+        assert_eq!(dest(distribution, "Release/chrome-sandbox"), None);
+        assert_eq!(dest(distribution, "Debug/libcef.so"), None);
+        assert_eq!(dest(distribution, "tests/cefclient/cefclient.cc"), None);
+    }
+
+    assert_eq!(dest(Distribution::Minimal, "include/cef_app.h"), None);
+    assert_eq!(dest(Distribution::Minimal, "CMakeLists.txt"), None);
+
+    assert_eq!(
+        dest(Distribution::Standard, "include/cef_app.h").as_deref(),
+        Some("sdk/include/cef_app.h")
+    );
+    assert_eq!(
+        dest(
+            Distribution::Standard,
+            "libcef_dll/wrapper/cef_closure_task.cc"
+        )
+        .as_deref(),
+        Some("sdk/libcef_dll/wrapper/cef_closure_task.cc")
+    );
+    assert_eq!(
+        dest(Distribution::Standard, "CMakeLists.txt").as_deref(),
+        Some("sdk/CMakeLists.txt")
+    );
+}
+
+#[test]
+fn runtime_layout_per_os() {
+    let dest = |os: Os, path: &str| {
+        runtime_destination(os, Path::new(path)).map(|p| p.to_string_lossy().into_owned())
+    };
+
+    assert_eq!(
+        dest(Os::Windows, "Release/libcef.dll").as_deref(),
+        Some("libcef.dll")
+    );
+    assert_eq!(
+        dest(Os::Windows, "Resources/icudtl.dat").as_deref(),
+        Some("icudtl.dat")
+    );
+    assert_eq!(
+        dest(
+            Os::Mac,
+            "Release/Chromium Embedded Framework.framework/Resources/en.lproj/locale.pak"
+        )
+        .as_deref(),
+        Some("Resources/en.lproj/locale.pak")
+    );
+    assert_eq!(dest(Os::Mac, "Release/libcef.dll"), None);
+    assert_eq!(
+        dest(
+            Os::Linux,
+            "Release/Chromium Embedded Framework.framework/x.pak"
+        ),
+        None
+    );
+}
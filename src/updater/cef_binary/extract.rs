@@ -0,0 +1,482 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result, bail};
+use filetime::FileTime;
+use tar::{EntryType, Header};
+use tracing::*;
+
+#[cfg(target_os = "linux")]
+use crate::updater::elf_patch::ElfPatch;
+use crate::updater::{
+    events::{self, UpdateEvent},
+    human_bytes,
+    progress::Throttle,
+};
+
+/// Split an archive path into its top level `cef_binary_*` directory and the
+/// rest, refusing anything that could point outside the directory we extract
+/// into.
+fn split_entry_path(path: &Path) -> Result<(String, PathBuf)> {
+    let mut top = None;
+    let mut rest = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                if top.is_none() {
+                    let name = part
+                        .to_str()
+                        .with_context(|| format!("non UTF-8 archive path {path:?}"))?;
+                    top = Some(name.to_string());
+                } else {
+                    rest.push(part);
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => bail!("archive path {path:?} contains \"..\""),
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("archive path {path:?} is absolute")
+            }
+        }
+    }
+
+    let top = top.with_context(|| format!("empty archive path {path:?}"))?;
+    Ok((top, rest))
+}
+
+/// Whether a symlink at `link` (relative to the extraction directory) pointing
+/// at `target` stays inside the extraction directory.
+fn symlink_stays_inside(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// The symlink extracted earlier (relative to the extraction directory) that
+/// `path` would be written through, if any. `symlink_stays_inside` only looks
+/// at the text of a path, which no longer matches the disk once one of its
+/// directories is a link.
+fn symlink_ancestor<'a>(path: &Path, symlinks: &'a BTreeSet<PathBuf>) -> Option<&'a Path> {
+    path.ancestors()
+        .skip(1)
+        .find_map(|ancestor| symlinks.get(ancestor))
+        .map(PathBuf::as_path)
+}
+
+/// What an extracted entry should look like once the whole archive is
+/// unpacked; `None` fields aren't checked.
+#[derive(Debug)]
+struct Expected {
+    kind: ExpectedKind,
+    mode: Option<u32>,
+    mtime: Option<i64>,
+}
+
+#[derive(Debug)]
+enum ExpectedKind {
+    File { size: Option<u64> },
+    Dir,
+    Symlink { target: PathBuf },
+}
+
+/// Permission bits we restore; setuid/setgid (e.g. on `chrome-sandbox`)
+/// can't be honoured by an unprivileged game anyway.
+const MODE_MASK: u32 = 0o777;
+
+fn header_mode(header: &Header) -> Result<u32> {
+    Ok(header.mode()? & MODE_MASK)
+}
+
+/// Same as `tar`, which bumps 0 to 1 so tools don't treat the file as
+/// missing a timestamp.
+fn header_mtime(header: &Header) -> Result<i64> {
+    Ok((header.mtime()? as i64).max(1))
+}
+
+/// Untar `reader` into `dest_dir`. Every entry must live under the same
+/// top-level directory, which is removed; `destination` maps the rest of the
+/// path to where it goes inside `dest_dir`, or `None` to skip it.
+///
+/// File modes, symlinks (within `dest_dir`) and mtimes are kept, and the
+/// resulting tree is checked against the archive's metadata at the end.
+///
+/// Malformed or malicious archives (absolute paths, `..`, links escaping
+/// `dest_dir`, device nodes) are an error, never a panic.
+pub fn unpack(
+    reader: impl io::Read,
+    component: &str,
+    dest_dir: &Path,
+    destination: impl Fn(&Path) -> Option<PathBuf>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(false);
+    archive.set_preserve_mtime(true);
+
+    let mut expected = BTreeMap::new();
+    // applied after everything else, since creating the files inside a
+    // directory changes its mtime (and a read-only mode would stop us)
+    let mut directories = Vec::new();
+    // entries written so far, and how many of those were last reported
+    let mut files = 0;
+    let mut reported = 0;
+    // relative paths of the symlinks extracted so far, which nothing may be
+    // written through
+    let mut symlinks = BTreeSet::new();
+
+    let mut top_name: Option<String> = None;
+    let mut check_top = |top: String| -> Result<()> {
+        match &top_name {
+            Some(expected) if *expected != top => {
+                bail!("archive has more than one top level directory ({expected:?}, {top:?})")
+            }
+            Some(_) => Ok(()),
+            None => {
+                top_name = Some(top);
+                Ok(())
+            }
+        }
+    };
+
+    let emit_extracting = |files| {
+        events::emit(UpdateEvent::Extracting {
+            component: component.to_string(),
+            files,
+        })
+    };
+    let throttle = Throttle::new();
+
+    for file in archive.entries()? {
+        let mut file = file?;
+
+        let entry_type = file.header().entry_type();
+        match entry_type {
+            EntryType::Regular
+            | EntryType::Continuous
+            | EntryType::Directory
+            | EntryType::Symlink
+            | EntryType::Link => {}
+            // pax metadata for the whole archive, nothing to extract
+            EntryType::XGlobalHeader => continue,
+            other => bail!("unsupported archive entry type {other:?}"),
+        }
+
+        let path = file.path()?.into_owned();
+        let (top, trimmed_path) = split_entry_path(&path)?;
+        check_top(top)?;
+
+        let Some(relative_path) = destination(&trimmed_path) else {
+            continue;
+        };
+        if let Some(link) = symlink_ancestor(&relative_path, &symlinks)
+            .or_else(|| symlinks.get(&relative_path).map(PathBuf::as_path))
+        {
+            bail!("archive path {path:?} goes through symlink {link:?}");
+        }
+
+        files += 1;
+        if reported == 0 || throttle.due() {
+            emit_extracting(files);
+            reported = files;
+        }
+        let out_path = dest_dir.join(&relative_path);
+        debug!("{:?} {:?}", path, out_path);
+
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create_dir_all {:?}", parent))?;
+        }
+
+        let mode = header_mode(file.header())?;
+        let mtime = header_mtime(file.header())?;
+
+        match entry_type {
+            EntryType::Directory => {
+                fs::create_dir_all(&out_path)
+                    .with_context(|| format!("create_dir_all {:?}", out_path))?;
+                directories.push((out_path.clone(), mode, mtime));
+                expected.insert(
+                    out_path,
+                    Expected {
+                        kind: ExpectedKind::Dir,
+                        mode: Some(mode),
+                        mtime: Some(mtime),
+                    },
+                );
+            }
+
+            EntryType::Symlink => {
+                let target = file
+                    .link_name()?
+                    .map(|target| target.into_owned())
+                    .with_context(|| format!("symlink {path:?} has no target"))?;
+                if !symlink_stays_inside(&relative_path, &target) {
+                    bail!("symlink {path:?} -> {target:?} points outside the archive");
+                }
+                file.unpack(&out_path)
+                    .with_context(|| format!("unpack {:?}", out_path))?;
+                symlinks.insert(relative_path);
+                expected.insert(
+                    out_path,
+                    Expected {
+                        kind: ExpectedKind::Symlink { target },
+                        mode: None,
+                        mtime: None,
+                    },
+                );
+            }
+
+            EntryType::Link => {
+                // the target is an archive path; tar would resolve it against
+                // the current directory, so map it like any other entry
+                let target = file
+                    .link_name()?
+                    .map(|target| target.into_owned())
+                    .with_context(|| format!("hard link {path:?} has no target"))?;
+                let (target_top, target_trimmed) = split_entry_path(&target)?;
+                check_top(target_top)?;
+                let target_relative = destination(&target_trimmed).with_context(|| {
+                    format!("hard link {path:?} -> {target:?} points at a file we don't extract")
+                })?;
+                if let Some(link) = symlink_ancestor(&target_relative, &symlinks) {
+                    bail!("hard link {path:?} -> {target:?} goes through symlink {link:?}");
+                }
+
+                let target_path = dest_dir.join(target_relative);
+                fs::hard_link(&target_path, &out_path)
+                    .with_context(|| format!("hard_link {target_path:?} {out_path:?}"))?;
+                // shares the target's inode, so its mode and mtime
+                expected.insert(
+                    out_path,
+                    Expected {
+                        kind: ExpectedKind::File { size: None },
+                        mode: None,
+                        mtime: None,
+                    },
+                );
+            }
+
+            _ => {
+                file.unpack(&out_path)
+                    .with_context(|| format!("unpack {:?}", out_path))?;
+
+                let size = file.header().size()?;
+                let stripped = strip_library(&out_path, mtime, component)?;
+                let patched = patch_binary(&out_path, mtime)?;
+
+                expected.insert(
+                    out_path,
+                    Expected {
+                        kind: ExpectedKind::File {
+                            size: (!stripped && !patched).then_some(size),
+                        },
+                        mode: Some(mode),
+                        mtime: Some(mtime),
+                    },
+                );
+            }
+        }
+    }
+
+    if reported != files {
+        emit_extracting(files);
+    }
+
+    // deepest first, so setting a parent's mtime is the last change to it
+    directories.sort_by(|(a, ..), (b, ..)| b.cmp(a));
+    for (path, mode, mtime) in directories {
+        set_mode(&path, mode)?;
+        filetime::set_file_mtime(&path, FileTime::from_unix_time(mtime, 0))
+            .with_context(|| format!("set_file_mtime {:?}", path))?;
+    }
+
+    check_symlinks(dest_dir, &symlinks)?;
+    verify(&expected)?;
+
+    Ok(())
+}
+
+/// Resolve every extracted symlink on disk: a target that looked fine as text
+/// can still leave `dest_dir` through a link extracted after it.
+fn check_symlinks(dest_dir: &Path, symlinks: &BTreeSet<PathBuf>) -> Result<()> {
+    if symlinks.is_empty() {
+        return Ok(());
+    }
+
+    let dest_dir =
+        fs::canonicalize(dest_dir).with_context(|| format!("canonicalize {dest_dir:?}"))?;
+    for link in symlinks {
+        // dangling links were checked as text and can't be followed anyway
+        let Ok(resolved) = fs::canonicalize(dest_dir.join(link)) else {
+            continue;
+        };
+        if !resolved.starts_with(&dest_dir) {
+            bail!("symlink {link:?} resolves to {resolved:?}, outside the archive");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("set_permissions {:?}", path))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & MODE_MASK)
+}
+
+/// Windows only has a read-only flag, which `tar` already derives from the mode.
+#[cfg(not(unix))]
+fn mode_of(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Check the extracted tree against the archive's metadata, catching a
+/// filesystem that silently dropped something (e.g. symlinks or exec bits).
+fn verify(expected: &BTreeMap<PathBuf, Expected>) -> Result<()> {
+    for (path, expected) in expected {
+        let metadata = path
+            .symlink_metadata()
+            .with_context(|| format!("{path:?} is missing after extraction"))?;
+
+        match &expected.kind {
+            ExpectedKind::File { size } => {
+                if !metadata.is_file() {
+                    bail!("{path:?} should be a file");
+                }
+                if let Some(size) = *size
+                    && metadata.len() != size
+                {
+                    bail!("{path:?} is {} bytes, expected {size}", metadata.len());
+                }
+            }
+            ExpectedKind::Dir => {
+                if !metadata.is_dir() {
+                    bail!("{path:?} should be a directory");
+                }
+            }
+            ExpectedKind::Symlink { target } => {
+                if !metadata.is_symlink() {
+                    bail!("{path:?} should be a symlink");
+                }
+                let actual = fs::read_link(path).with_context(|| format!("read_link {path:?}"))?;
+                if actual != *target {
+                    bail!("{path:?} points at {actual:?}, expected {target:?}");
+                }
+            }
+        }
+
+        if let (Some(mode), Some(actual)) = (expected.mode, mode_of(&metadata))
+            && actual != mode
+        {
+            bail!("{path:?} has mode {actual:o}, expected {mode:o}");
+        }
+
+        if let Some(mtime) = expected.mtime {
+            let actual = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs() as i64);
+            if actual != Some(mtime) {
+                bail!("{path:?} has mtime {actual:?}, expected {mtime}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Strip shared libraries in place, keeping their mtime; `true` if the file
+/// was rewritten. Only saves disk space, so a failure just leaves the
+/// library as it was.
+#[cfg(target_os = "linux")]
+fn strip_library(path: &Path, mtime: i64, component: &str) -> Result<bool> {
+    if path.extension().is_none_or(|ext| ext != "so") {
+        return Ok(false);
+    }
+
+    debug!("stripping {:?}", path);
+    events::emit(UpdateEvent::Stripping {
+        component: component.to_string(),
+        file: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    });
+    match crate::elf::strip(path) {
+        Ok(0) => return Ok(false),
+        Ok(saved) => debug!("stripped {} from {:?}", human_bytes(saved), path),
+        Err(e) => {
+            warn!("couldn't strip {:?}: {:#}", path, e);
+            return Ok(false);
+        }
+    }
+
+    filetime::set_file_mtime(path, FileTime::from_unix_time(mtime, 0))
+        .with_context(|| format!("set_file_mtime {:?}", path))?;
+
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn strip_library(_path: &Path, _mtime: i64, _component: &str) -> Result<bool> {
+    Ok(false)
+}
+
+/// Point executables and libraries at the configured dynamic linker and
+/// library path (see `ElfPatch`), keeping their mtime; `true` if the file was
+/// rewritten. Unlike stripping, they won't run without it, so failing is an
+/// error.
+#[cfg(target_os = "linux")]
+fn patch_binary(path: &Path, mtime: i64) -> Result<bool> {
+    let Some(patch) = ElfPatch::from_env() else {
+        return Ok(false);
+    };
+    if !patch.apply(path)? {
+        return Ok(false);
+    }
+
+    filetime::set_file_mtime(path, FileTime::from_unix_time(mtime, 0))
+        .with_context(|| format!("set_file_mtime {:?}", path))?;
+
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn patch_binary(_path: &Path, _mtime: i64) -> Result<bool> {
+    Ok(false)
+}
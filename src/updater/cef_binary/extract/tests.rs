@@ -0,0 +1,346 @@
+use std::path::PathBuf;
+
+use tar::{Builder, Header};
+
+use super::*;
+use crate::test_util::scratch_dir;
+
+/// Header with the raw `path` bytes, bypassing `tar`'s own path validation so
+/// we can build the archives a malicious server could send.
+fn header(path: &str, entry_type: EntryType, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_cksum();
+    header
+}
+
+fn link(path: &str, entry_type: EntryType, target: &str) -> Header {
+    let mut header = header(path, entry_type, 0);
+    header.set_link_name(target).unwrap();
+    header.set_cksum();
+    header
+}
+
+fn archive(entries: Vec<(Header, &[u8])>) -> Vec<u8> {
+    let mut builder = Builder::new(Vec::new());
+    for (header, data) in entries {
+        builder.append(&header, data).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+fn file(path: &str, data: &'static [u8]) -> (Header, &'static [u8]) {
+    (header(path, EntryType::Regular, data.len() as u64), data)
+}
+
+fn unpack_into(name: &str, entries: Vec<(Header, &[u8])>) -> (PathBuf, Result<()>) {
+    let dir = scratch_dir(name);
+    let data = archive(entries);
+    let result = unpack(data.as_slice(), "test", &dir, |path| {
+        (!path.as_os_str().is_empty()).then(|| path.to_path_buf())
+    });
+    (dir, result)
+}
+
+#[test]
+fn extracts_under_the_top_directory() {
+    let (dir, result) = unpack_into(
+        "ok",
+        vec![
+            (header("cef_binary_1/", EntryType::Directory, 0), b""),
+            file("./cef_binary_1/Release/libcef.pak", b"elf"),
+            file("cef_binary_1/README.txt", b"readme"),
+        ],
+    );
+    result.unwrap();
+
+    assert_eq!(fs::read(dir.join("Release/libcef.pak")).unwrap(), b"elf");
+    assert_eq!(fs::read(dir.join("README.txt")).unwrap(), b"readme");
+}
+
+#[test]
+fn extracting_events_are_throttled() {
+    let dir = scratch_dir("throttle");
+    let names: Vec<_> = (0..1000).map(|i| format!("cef_binary_1/{i}.txt")).collect();
+    let data = archive(names.iter().map(|name| file(name, b"x")).collect());
+
+    // skipped entries aren't counted
+    let mut rx = events::subscribe();
+    unpack(data.as_slice(), "throttle-test", &dir, |path| {
+        (!path.to_string_lossy().starts_with('1')).then(|| path.to_path_buf())
+    })
+    .unwrap();
+
+    let mut counts = Vec::new();
+    while let Ok(event) = rx.try_recv() {
+        if let UpdateEvent::Extracting { component, files } = event
+            && component == "throttle-test"
+        {
+            counts.push(files);
+        }
+    }
+    assert_eq!(counts.first(), Some(&1));
+    assert_eq!(counts.last(), Some(&889));
+    assert!(counts.len() < 100, "{} events", counts.len());
+}
+
+#[test]
+fn rejects_escaping_paths() {
+    for (name, path) in [
+        ("absolute", "/tmp/cef-loader-extract-evil"),
+        ("parent", "cef_binary_1/../../evil"),
+        ("leading-parent", "../evil"),
+    ] {
+        let (dir, result) = unpack_into(name, vec![file(path, b"evil")]);
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(
+            error.contains("absolute") || error.contains(".."),
+            "{path}: {error}"
+        );
+        assert!(!dir.join("evil").exists());
+    }
+    assert!(!Path::new("/tmp/cef-loader-extract-evil").exists());
+}
+
+#[test]
+fn rejects_mixed_top_directories() {
+    let (_dir, result) = unpack_into(
+        "mixed",
+        vec![
+            file("cef_binary_1/README.txt", b"readme"),
+            file("cef_binary_2/LICENSE.txt", b"license"),
+        ],
+    );
+    assert!(format!("{:#}", result.unwrap_err()).contains("more than one top level directory"));
+}
+
+#[test]
+fn rejects_devices() {
+    for (name, entry_type) in [
+        ("char", EntryType::Char),
+        ("block", EntryType::Block),
+        ("fifo", EntryType::Fifo),
+    ] {
+        let (dir, result) =
+            unpack_into(name, vec![(header("cef_binary_1/dev", entry_type, 0), b"")]);
+        assert!(format!("{:#}", result.unwrap_err()).contains("unsupported"));
+        assert!(!dir.join("dev").exists());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_must_stay_inside() {
+    let (dir, result) = unpack_into(
+        "symlink-ok",
+        vec![
+            file("cef_binary_1/Release/libcef.pak", b"elf"),
+            (
+                link(
+                    "cef_binary_1/lib/libcef.pak",
+                    EntryType::Symlink,
+                    "../Release/libcef.pak",
+                ),
+                b"",
+            ),
+        ],
+    );
+    result.unwrap();
+    assert_eq!(fs::read(dir.join("lib/libcef.pak")).unwrap(), b"elf");
+
+    for (name, target) in [
+        ("symlink-parent", "../../../etc/passwd"),
+        ("symlink-absolute", "/etc/passwd"),
+    ] {
+        let (dir, result) = unpack_into(
+            name,
+            vec![(
+                link("cef_binary_1/lib/passwd", EntryType::Symlink, target),
+                b"",
+            )],
+        );
+        assert!(format!("{:#}", result.unwrap_err()).contains("points outside"));
+        assert!(dir.join("lib/passwd").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn rejects_writing_through_symlinks() {
+    // each link looks fine as text, but `l/x` is really created in the
+    // extraction directory and points at its parent
+    let (dir, result) = unpack_into(
+        "symlink-chain",
+        vec![
+            (link("cef_binary_1/l", EntryType::Symlink, "."), b""),
+            (link("cef_binary_1/l/x", EntryType::Symlink, ".."), b""),
+            file("cef_binary_1/l/x/evil", b"evil"),
+        ],
+    );
+    assert!(format!("{:#}", result.unwrap_err()).contains("goes through symlink"));
+    assert!(dir.join("x").symlink_metadata().is_err());
+    assert!(!dir.parent().unwrap().join("evil").exists());
+
+    let (_dir, result) = unpack_into(
+        "symlink-hardlink",
+        vec![
+            (link("cef_binary_1/l", EntryType::Symlink, "."), b""),
+            (
+                link(
+                    "cef_binary_1/passwd",
+                    EntryType::Link,
+                    "cef_binary_1/l/passwd",
+                ),
+                b"",
+            ),
+        ],
+    );
+    assert!(format!("{:#}", result.unwrap_err()).contains("goes through symlink"));
+}
+
+#[cfg(unix)]
+#[test]
+fn rejects_symlinks_resolving_outside() {
+    // `x` is checked before `d/up` exists, so only resolving it on disk
+    // shows that it ends up above the extraction directory
+    let (_dir, result) = unpack_into(
+        "symlink-resolve",
+        vec![
+            (header("cef_binary_1/d/", EntryType::Directory, 0), b""),
+            (link("cef_binary_1/x", EntryType::Symlink, "d/up/.."), b""),
+            (link("cef_binary_1/d/up", EntryType::Symlink, ".."), b""),
+        ],
+    );
+    assert!(format!("{:#}", result.unwrap_err()).contains("outside the archive"));
+}
+
+#[test]
+fn hard_links_resolve_inside_the_archive() {
+    let (dir, result) = unpack_into(
+        "hardlink-ok",
+        vec![
+            file("cef_binary_1/Release/libcef.pak", b"elf"),
+            (
+                link(
+                    "cef_binary_1/libcef.pak",
+                    EntryType::Link,
+                    "cef_binary_1/Release/libcef.pak",
+                ),
+                b"",
+            ),
+        ],
+    );
+    result.unwrap();
+    assert_eq!(fs::read(dir.join("libcef.pak")).unwrap(), b"elf");
+
+    for (name, target) in [
+        ("hardlink-parent", "cef_binary_1/../../evil"),
+        ("hardlink-absolute", "/etc/passwd"),
+        ("hardlink-other-top", "cef_binary_2/Release/libcef.pak"),
+    ] {
+        let (dir, result) = unpack_into(
+            name,
+            vec![
+                file("cef_binary_1/Release/libcef.pak", b"elf"),
+                (link("cef_binary_1/passwd", EntryType::Link, target), b""),
+            ],
+        );
+        assert!(result.is_err(), "{target}");
+        assert!(!dir.join("passwd").exists());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_modes_and_mtimes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mtime_of = |path: &Path| {
+        FileTime::from_last_modification_time(&path.symlink_metadata().unwrap()).unix_seconds()
+    };
+
+    let mut sandbox = header("cef_binary_1/Release/chrome-sandbox", EntryType::Regular, 3);
+    sandbox.set_mode(0o4755);
+    sandbox.set_mtime(1_700_000_000);
+    sandbox.set_cksum();
+
+    let mut release = header("cef_binary_1/Release/", EntryType::Directory, 0);
+    release.set_mode(0o750);
+    release.set_mtime(1_600_000_000);
+    release.set_cksum();
+
+    let mut symlink = link(
+        "cef_binary_1/Release/sandbox",
+        EntryType::Symlink,
+        "chrome-sandbox",
+    );
+    symlink.set_mtime(1_650_000_000);
+    symlink.set_cksum();
+
+    let (dir, result) = unpack_into(
+        "modes",
+        vec![(release, b""), (sandbox, b"elf"), (symlink, b"")],
+    );
+    result.unwrap();
+
+    let sandbox = dir.join("Release/chrome-sandbox");
+    // setuid is dropped, the rest kept
+    assert_eq!(
+        sandbox.metadata().unwrap().permissions().mode() & 0o7777,
+        0o755
+    );
+    assert_eq!(mtime_of(&sandbox), 1_700_000_000);
+
+    let release = dir.join("Release");
+    assert_eq!(
+        release.metadata().unwrap().permissions().mode() & 0o7777,
+        0o750
+    );
+    assert_eq!(mtime_of(&release), 1_600_000_000);
+
+    let symlink = dir.join("Release/sandbox");
+    assert_eq!(
+        fs::read_link(&symlink).unwrap(),
+        Path::new("chrome-sandbox")
+    );
+    assert_eq!(mtime_of(&symlink), 1_650_000_000);
+}
+
+#[test]
+fn verify_catches_mismatches() {
+    let dir = scratch_dir("verify");
+    let path = dir.join("libcef.pak");
+    fs::write(&path, b"pak").unwrap();
+    filetime::set_file_mtime(&path, FileTime::from_unix_time(1_700_000_000, 0)).unwrap();
+
+    let check = |kind, mtime| {
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            path.clone(),
+            Expected {
+                kind,
+                mode: None,
+                mtime,
+            },
+        );
+        verify(&expected)
+    };
+
+    check(ExpectedKind::File { size: Some(3) }, Some(1_700_000_000)).unwrap();
+    check(ExpectedKind::File { size: None }, None).unwrap();
+    assert!(check(ExpectedKind::File { size: Some(4) }, None).is_err());
+    assert!(check(ExpectedKind::File { size: None }, Some(1)).is_err());
+    assert!(check(ExpectedKind::Dir, None).is_err());
+    assert!(
+        check(
+            ExpectedKind::Symlink {
+                target: PathBuf::from("x")
+            },
+            None
+        )
+        .is_err()
+    );
+}
@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests;
+
+use std::{fmt, pin::Pin};
+
+use anyhow::{Result, bail};
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZstdDecoder};
+use tokio::io::{AsyncRead, BufReader};
+
+/// Compression of a CEF tar archive. The CDN only serves `.tar.bz2`, but
+/// mirrors may repackage it into something faster to decompress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarBz2,
+    TarZst,
+    TarXz,
+    TarGz,
+    Tar,
+}
+
+const EXTENSIONS: &[(&str, ArchiveFormat)] = &[
+    (".tar.bz2", ArchiveFormat::TarBz2),
+    (".tbz2", ArchiveFormat::TarBz2),
+    (".tbz", ArchiveFormat::TarBz2),
+    (".tar.zst", ArchiveFormat::TarZst),
+    (".tzst", ArchiveFormat::TarZst),
+    (".tar.xz", ArchiveFormat::TarXz),
+    (".txz", ArchiveFormat::TarXz),
+    (".tar.gz", ArchiveFormat::TarGz),
+    (".tgz", ArchiveFormat::TarGz),
+    (".tar", ArchiveFormat::Tar),
+];
+
+const CONTENT_TYPES: &[(&str, ArchiveFormat)] = &[
+    ("application/x-bzip2", ArchiveFormat::TarBz2),
+    ("application/x-bzip", ArchiveFormat::TarBz2),
+    ("application/zstd", ArchiveFormat::TarZst),
+    ("application/x-zstd", ArchiveFormat::TarZst),
+    ("application/x-xz", ArchiveFormat::TarXz),
+    ("application/gzip", ArchiveFormat::TarGz),
+    ("application/x-gzip", ArchiveFormat::TarGz),
+    ("application/x-tar", ArchiveFormat::Tar),
+];
+
+impl ArchiveFormat {
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_ascii_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(extension, _)| file_name.ends_with(extension))
+            .map(|(_, format)| *format)
+    }
+
+    /// `None` for generic types like `application/octet-stream`.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        CONTENT_TYPES
+            .iter()
+            .find(|(name, _)| *name == mime)
+            .map(|(_, format)| *format)
+    }
+
+    /// The file name decides, since servers often send a generic or wrong
+    /// `Content-Type`; the header only helps when the name says nothing.
+    pub fn detect(file_name: &str, content_type: Option<&str>) -> Result<Self> {
+        if let Some(format) = Self::from_file_name(file_name) {
+            return Ok(format);
+        }
+        match content_type.and_then(Self::from_content_type) {
+            Some(format) => Ok(format),
+            None => bail!(
+                "can't tell the archive format of {file_name:?} (Content-Type {content_type:?})"
+            ),
+        }
+    }
+
+    /// Wrap `reader` so it yields the plain tar stream.
+    pub fn decoder<'a, R>(self, reader: R) -> Pin<Box<dyn AsyncRead + Send + 'a>>
+    where
+        R: AsyncRead + Send + 'a,
+    {
+        let reader = BufReader::new(reader);
+        match self {
+            Self::TarBz2 => Box::pin(BzDecoder::new(reader)),
+            Self::TarZst => Box::pin(ZstdDecoder::new(reader)),
+            Self::TarXz => Box::pin(XzDecoder::new(reader)),
+            Self::TarGz => {
+                // concatenated .gz files are still one valid stream
+                let mut decoder = GzipDecoder::new(reader);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Self::Tar => Box::pin(reader),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TarBz2 => "tar.bz2",
+            Self::TarZst => "tar.zst",
+            Self::TarXz => "tar.xz",
+            Self::TarGz => "tar.gz",
+            Self::Tar => "tar",
+        })
+    }
+}
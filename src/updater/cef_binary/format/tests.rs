@@ -0,0 +1,68 @@
+use std::io::Write;
+
+use tokio::io::AsyncReadExt;
+
+use super::*;
+
+#[test]
+fn detects_by_file_name() {
+    for (name, format) in [
+        (
+            "cef_binary_139.0.40+g465474a_linux64_minimal.tar.bz2",
+            ArchiveFormat::TarBz2,
+        ),
+        (
+            "cef_binary_139_linux64_minimal.tar.zst",
+            ArchiveFormat::TarZst,
+        ),
+        ("cef_binary_139_linux64_minimal.TXZ", ArchiveFormat::TarXz),
+        ("cef_binary_139_linux64_minimal.tgz", ArchiveFormat::TarGz),
+        ("cef_binary_139_linux64_minimal.tar", ArchiveFormat::Tar),
+    ] {
+        assert_eq!(ArchiveFormat::from_file_name(name), Some(format), "{name}");
+    }
+    assert_eq!(ArchiveFormat::from_file_name("cef_binary.zip"), None);
+    assert_eq!(ArchiveFormat::from_file_name("cef_binary"), None);
+}
+
+#[test]
+fn falls_back_to_content_type() {
+    assert_eq!(
+        ArchiveFormat::detect("cef_binary", Some("application/zstd")).unwrap(),
+        ArchiveFormat::TarZst
+    );
+    assert_eq!(
+        ArchiveFormat::detect("cef_binary", Some("Application/X-XZ; charset=binary")).unwrap(),
+        ArchiveFormat::TarXz
+    );
+    // the name wins over a wrong header
+    assert_eq!(
+        ArchiveFormat::detect("cef_binary.tar.bz2", Some("application/gzip")).unwrap(),
+        ArchiveFormat::TarBz2
+    );
+
+    assert!(ArchiveFormat::detect("cef_binary", Some("application/octet-stream")).is_err());
+    assert!(ArchiveFormat::detect("cef_binary", None).is_err());
+}
+
+async fn decode(format: ArchiveFormat, data: Vec<u8>) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    format
+        .decoder(std::io::Cursor::new(data))
+        .read_to_end(&mut decoded)
+        .await
+        .unwrap();
+    decoded
+}
+
+#[tokio::test]
+async fn decodes_tar_and_bz2() {
+    let tar = b"not really a tar, the decoder doesn't care".repeat(100);
+
+    assert_eq!(decode(ArchiveFormat::Tar, tar.clone()).await, tar);
+
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+    encoder.write_all(&tar).unwrap();
+    let bz2 = encoder.finish().unwrap();
+    assert_eq!(decode(ArchiveFormat::TarBz2, bz2).await, tar);
+}
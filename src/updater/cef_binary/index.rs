@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashMap, env};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use tracing::*;
+
+use super::{CEF_ARCH, distribution::Distribution};
+use crate::updater::{compat::Version, make_client};
+
+const CEF_BUILDS_URL: &str = "https://cef-builds.spotifycdn.com";
+
+/// Base URL of a mirror of the CEF builds CDN, serving `index.json` and the
+/// archives it lists (which may be repackaged, see `format::ArchiveFormat`).
+pub const MIRROR_ENV: &str = "CEF_LOADER_CEF_MIRROR";
+
+/// File type of the debug symbols for the `Release` binaries.
+const SYMBOLS_TYPE: &str = "release_symbols";
+
+/// `index.json` from the CEF builds CDN, keyed by platform (`CEF_ARCH`).
+#[derive(Debug, Deserialize)]
+pub struct Index(HashMap<String, Platform>);
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+    versions: Vec<IndexVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexVersion {
+    cef_version: String,
+    channel: String,
+    files: Vec<IndexFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexFile {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+}
+
+/// A CEF binary archive that exists on the CDN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CefBuild {
+    pub version: String,
+    pub distribution: Distribution,
+    pub file_name: String,
+    pub symbols_file_name: Option<String>,
+    /// The version asked for, when this platform's builds were discontinued
+    /// before it and this is the last one it got instead.
+    pub substitutes: Option<String>,
+}
+
+impl CefBuild {
+    /// The archive names the CDN uses when the index can't tell us.
+    pub fn assumed(version: &str, arch: &str, distribution: Distribution) -> Self {
+        Self {
+            version: version.to_string(),
+            distribution,
+            file_name: format!("cef_binary_{version}_{arch}_{distribution}.tar.bz2"),
+            symbols_file_name: Some(format!(
+                "cef_binary_{version}_{arch}_{SYMBOLS_TYPE}.tar.bz2"
+            )),
+            substitutes: None,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        file_url(&self.file_name)
+    }
+
+    pub fn symbols_url(&self) -> Option<String> {
+        self.symbols_file_name.as_deref().map(file_url)
+    }
+}
+
+fn builds_url() -> String {
+    env::var(MIRROR_ENV)
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| CEF_BUILDS_URL.to_string())
+}
+
+fn file_url(file_name: &str) -> String {
+    format!("{}/{}", builds_url(), file_name.replace('+', "%2B"))
+}
+
+impl Index {
+    pub fn parse(text: &str) -> Result<Self> {
+        serde_json::from_str(text).context("invalid CEF builds index.json")
+    }
+
+    /// Pick the build of `wanted` for `arch`: the exact version, on any
+    /// channel. CEF has no stable ABI between builds, so nothing else will do,
+    /// except when `arch` stopped getting builds before `wanted` (e.g. linux32
+    /// after 101): the plugin is built against its newest stable build then,
+    /// which is returned with `substitutes` set.
+    pub fn resolve(
+        &self,
+        arch: &str,
+        wanted: &str,
+        distribution: Distribution,
+    ) -> Result<CefBuild> {
+        let Some(platform) = self.0.get(arch) else {
+            bail!("CEF builds index has no {arch} builds");
+        };
+
+        let builds = platform.versions.iter().filter_map(|version| {
+            let file_of = |kind: &str| {
+                version
+                    .files
+                    .iter()
+                    .find(|f| f.kind == kind)
+                    .map(|f| f.name.clone())
+            };
+            let build = CefBuild {
+                version: version.cef_version.clone(),
+                distribution,
+                file_name: file_of(distribution.index_type())?,
+                symbols_file_name: file_of(SYMBOLS_TYPE),
+                substitutes: None,
+            };
+            Some((version, build))
+        });
+
+        let mut stable = Vec::new();
+        let mut newest_major = None;
+        for (version, build) in builds {
+            if version.cef_version == wanted {
+                return Ok(build);
+            }
+            let Ok(parsed) = Version::parse(&version.cef_version) else {
+                continue;
+            };
+            newest_major = newest_major.max(Some(parsed.major));
+            if version.channel == "stable" {
+                stable.push((parsed, build));
+            }
+        }
+
+        let target = Version::parse(wanted)?;
+
+        // only when nothing at all (not even a beta) has been built since
+        let discontinued = newest_major.is_some_and(|major| major < target.major);
+        match stable.into_iter().max_by_key(|(v, _)| *v) {
+            Some((_, build)) if discontinued => {
+                warn!(
+                    "{arch} CEF builds were discontinued at {}, using it instead of {wanted}",
+                    build.version
+                );
+                Ok(CefBuild {
+                    substitutes: Some(wanted.to_string()),
+                    ..build
+                })
+            }
+            _ => bail!("no CEF {wanted} build for {arch}"),
+        }
+    }
+}
+
+pub async fn fetch() -> Result<Index> {
+    let text = make_client()
+        .get(format!("{}/index.json", builds_url()))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Index::parse(&text)
+}
+
+/// Resolve `wanted` against the CDN index for this platform. If the index
+/// can't be fetched we still try the exact version, which is what the CDN
+/// almost always has.
+pub async fn resolve(wanted: &str, distribution: Distribution) -> Result<CefBuild> {
+    match fetch().await {
+        Ok(index) => index.resolve(CEF_ARCH, wanted, distribution),
+        Err(e) => {
+            warn!(
+                "couldn't fetch CEF builds index, assuming {wanted} exists: {:#}",
+                e
+            );
+            Ok(CefBuild::assumed(wanted, CEF_ARCH, distribution))
+        }
+    }
+}
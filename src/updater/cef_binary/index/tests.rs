@@ -0,0 +1,157 @@
+use super::*;
+
+const INDEX: &str = r#"{
+    "linux32": {
+        "versions": [
+            {
+                "cef_version": "101.0.18+g367b4a0+chromium-101.0.4951.67",
+                "channel": "stable",
+                "files": [
+                    { "type": "standard", "name": "cef_binary_101.0.18+g367b4a0+chromium-101.0.4951.67_linux32.tar.bz2" },
+                    { "type": "release_symbols", "name": "cef_binary_101.0.18+g367b4a0+chromium-101.0.4951.67_linux32_release_symbols.tar.bz2" },
+                    { "type": "minimal", "name": "cef_binary_101.0.18+g367b4a0+chromium-101.0.4951.67_linux32_minimal.tar.bz2" }
+                ]
+            },
+            {
+                "cef_version": "100.0.24+g0783cf8+chromium-100.0.4896.127",
+                "channel": "stable",
+                "files": [
+                    { "type": "minimal", "name": "cef_binary_100.0.24+g0783cf8+chromium-100.0.4896.127_linux32_minimal.tar.bz2" }
+                ]
+            }
+        ]
+    },
+    "linux64": {
+        "versions": [
+            {
+                "cef_version": "139.0.40+g465474a+chromium-139.0.7258.139",
+                "channel": "stable",
+                "files": [
+                    { "type": "minimal", "name": "cef_binary_139.0.40+g465474a+chromium-139.0.7258.139_linux64_minimal.tar.bz2" }
+                ]
+            },
+            {
+                "cef_version": "139.0.28+g55ab8a8+chromium-139.0.7258.139",
+                "channel": "stable",
+                "files": [
+                    { "type": "minimal", "name": "cef_binary_139.0.28+g55ab8a8+chromium-139.0.7258.139_linux64_minimal.tar.bz2" }
+                ]
+            },
+            {
+                "cef_version": "139.0.17+g6c61db3+chromium-139.0.7258.139",
+                "channel": "stable",
+                "files": [
+                    { "type": "minimal", "name": "cef_binary_139.0.17+g6c61db3+chromium-139.0.7258.139_linux64_minimal.tar.bz2" }
+                ]
+            },
+            {
+                "cef_version": "140.0.1+gabcdef0+chromium-140.0.7339.16",
+                "channel": "beta",
+                "files": [
+                    { "type": "minimal", "name": "cef_binary_140.0.1+gabcdef0+chromium-140.0.7339.16_linux64_minimal.tar.bz2" }
+                ]
+            }
+        ]
+    }
+}"#;
+
+fn resolve(arch: &str, wanted: &str) -> Result<String> {
+    Index::parse(INDEX)
+        .unwrap()
+        .resolve(arch, wanted, Distribution::Minimal)
+        .map(|build| build.version)
+}
+
+#[test]
+fn exact_version_on_any_channel() {
+    let index = Index::parse(INDEX).unwrap();
+    let build = index
+        .resolve(
+            "linux64",
+            "139.0.28+g55ab8a8+chromium-139.0.7258.139",
+            Distribution::Minimal,
+        )
+        .unwrap();
+    assert_eq!(
+        build.file_name,
+        "cef_binary_139.0.28+g55ab8a8+chromium-139.0.7258.139_linux64_minimal.tar.bz2"
+    );
+    assert!(build.url().contains("139.0.28%2Bg55ab8a8%2Bchromium"));
+
+    assert_eq!(
+        resolve("linux64", "140.0.1+gabcdef0+chromium-140.0.7339.16").unwrap(),
+        "140.0.1+gabcdef0+chromium-140.0.7339.16"
+    );
+}
+
+#[test]
+fn never_substitutes_another_build_of_the_same_major() {
+    // a different ABI, even if only the patch version differs
+    assert!(resolve("linux64", "139.0.30+g0000000+chromium-139.0.7258.139").is_err());
+    assert!(resolve("linux64", "139.0.10+g0000000+chromium-139.0.7258.100").is_err());
+    // beta builds are only used when asked for exactly
+    assert!(resolve("linux64", "140.0.2+g0000000+chromium-140.0.7339.20").is_err());
+}
+
+#[test]
+fn discontinued_arch_uses_its_last_build() {
+    let wanted = "139.0.40+g465474a+chromium-139.0.7258.139";
+    let build = Index::parse(INDEX)
+        .unwrap()
+        .resolve("linux32", wanted, Distribution::Minimal)
+        .unwrap();
+    assert_eq!(build.version, "101.0.18+g367b4a0+chromium-101.0.4951.67");
+    assert_eq!(build.substitutes.as_deref(), Some(wanted));
+
+    let exact = Index::parse(INDEX)
+        .unwrap()
+        .resolve("linux32", &build.version, Distribution::Minimal)
+        .unwrap();
+    assert_eq!(exact.substitutes, None);
+}
+
+#[test]
+fn unknown_arch_or_version() {
+    assert!(resolve("windowsarm64", "139.0.40").is_err());
+    // older than anything the arch has
+    assert!(resolve("linux32", "99.0.1").is_err());
+}
+
+#[test]
+fn picks_the_distribution_and_symbols() {
+    let index = Index::parse(INDEX).unwrap();
+    let wanted = "101.0.18+g367b4a0+chromium-101.0.4951.67";
+
+    let build = index
+        .resolve("linux32", wanted, Distribution::Standard)
+        .unwrap();
+    assert_eq!(
+        build.file_name,
+        "cef_binary_101.0.18+g367b4a0+chromium-101.0.4951.67_linux32.tar.bz2"
+    );
+    assert_eq!(
+        build.symbols_file_name.as_deref(),
+        Some("cef_binary_101.0.18+g367b4a0+chromium-101.0.4951.67_linux32_release_symbols.tar.bz2")
+    );
+
+    // 100.0.24 has no standard archive
+    assert!(
+        index
+            .resolve(
+                "linux32",
+                "100.0.24+g0783cf8+chromium-100.0.4896.127",
+                Distribution::Standard
+            )
+            .is_err()
+    );
+
+    let build = index
+        .resolve(
+            "linux32",
+            "100.0.24+g0783cf8+chromium-100.0.4896.127",
+            Distribution::Minimal,
+        )
+        .unwrap();
+    assert_eq!(build.symbols_file_name, None);
+    assert_eq!(build.symbols_url(), None);
+}
@@ -0,0 +1,198 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::BTreeSet,
+    env, fmt,
+    path::{Component, Path},
+};
+
+/// Comma separated languages to keep (e.g. `de,fr,pt-BR`), or `all`.
+/// Defaults to the system language.
+pub const LOCALES_ENV: &str = "CEF_LOADER_LOCALES";
+
+/// Always kept: CEF falls back to it for anything missing.
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// Which of CEF's locale packs to extract, by language (`pt` keeps both
+/// `pt-BR` and `pt-PT`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Locales {
+    All,
+    Languages(BTreeSet<String>),
+}
+
+/// `de_DE.UTF-8@euro`, `pt-BR` or `es_419.lproj` to `de`, `pt` or `es`.
+fn language_of(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_suffix(".lproj").unwrap_or(tag);
+    let language = tag
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    // "C" and "POSIX" mean no particular language
+    if language.is_empty() || language == "c" || language == "posix" {
+        None
+    } else {
+        Some(language)
+    }
+}
+
+impl Locales {
+    pub fn parse(s: &str) -> Self {
+        if s.trim().eq_ignore_ascii_case("all") {
+            return Self::All;
+        }
+
+        let mut languages: BTreeSet<String> =
+            s.split([',', ' ', ':']).filter_map(language_of).collect();
+        languages.insert(FALLBACK_LANGUAGE.to_string());
+        Self::Languages(languages)
+    }
+
+    /// `LOCALES_ENV` if set, otherwise the system language: from the
+    /// Windows/macOS settings (a game started from Explorer or Finder has no
+    /// `LANG`), or the usual POSIX variables.
+    pub fn from_env() -> Self {
+        if let Ok(value) = env::var(LOCALES_ENV) {
+            return Self::parse(&value);
+        }
+
+        if let Some(system) = system_languages().filter(|system| !system.is_empty()) {
+            return Self::parse(&system);
+        }
+
+        let system = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::parse(&system)
+    }
+
+    /// Whether everything `other` keeps is already in `self`.
+    pub fn contains(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::All, _) => true,
+            (Self::Languages(_), Self::All) => false,
+            (Self::Languages(ours), Self::Languages(theirs)) => theirs.is_subset(ours),
+        }
+    }
+
+    /// Whether to extract the archive entry at `path`; anything that isn't a
+    /// locale pack is always kept.
+    ///
+    /// Windows/Linux: `Resources/locales/de.pak`
+    /// macOS: `.../Chromium Embedded Framework.framework/Resources/de.lproj/locale.pak`
+    pub fn keeps(&self, path: &Path) -> bool {
+        let Self::Languages(languages) = self else {
+            return true;
+        };
+
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let Component::Normal(name) = component else {
+                continue;
+            };
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+
+            let locale = if name.ends_with(".lproj") {
+                Some(name)
+            } else if name == "locales" {
+                components
+                    .peek()
+                    .and_then(|next| next.as_os_str().to_str())
+                    .and_then(|next| next.strip_suffix(".pak"))
+            } else {
+                None
+            };
+
+            if let Some(locale) = locale {
+                return language_of(locale).is_some_and(|language| languages.contains(&language));
+            }
+        }
+
+        true
+    }
+}
+
+/// The user's locale, e.g. `de-DE`.
+#[cfg(windows)]
+fn system_languages() -> Option<String> {
+    use windows::Win32::Globalization::{GetUserDefaultLocaleName, LOCALE_NAME_MAX_LENGTH};
+
+    let mut buf = [0; LOCALE_NAME_MAX_LENGTH as usize];
+    let len = unsafe { GetUserDefaultLocaleName(&mut buf) };
+    // counts the terminating NUL, 0 on failure
+    let len = usize::try_from(len).ok()?.checked_sub(1)?;
+    String::from_utf16(&buf[..len]).ok()
+}
+
+/// The user's preferred languages in order, e.g. `de-DE,en-US`.
+#[cfg(target_os = "macos")]
+fn system_languages() -> Option<String> {
+    use std::ffi::{CStr, c_char, c_void};
+
+    type CFIndex = isize;
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        fn CFLocaleCopyPreferredLanguages() -> *const c_void;
+        fn CFArrayGetCount(array: *const c_void) -> CFIndex;
+        fn CFArrayGetValueAtIndex(array: *const c_void, index: CFIndex) -> *const c_void;
+        fn CFStringGetCString(
+            string: *const c_void,
+            buffer: *mut c_char,
+            size: CFIndex,
+            encoding: u32,
+        ) -> u8;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    let array = unsafe { CFLocaleCopyPreferredLanguages() };
+    if array.is_null() {
+        return None;
+    }
+
+    let mut languages = Vec::new();
+    for index in 0..unsafe { CFArrayGetCount(array) } {
+        let mut buf: [c_char; 64] = [0; 64];
+        let copied = unsafe {
+            CFStringGetCString(
+                CFArrayGetValueAtIndex(array, index),
+                buf.as_mut_ptr(),
+                buf.len() as CFIndex,
+                CF_STRING_ENCODING_UTF8,
+            )
+        };
+        if copied != 0 {
+            let language = unsafe { CStr::from_ptr(buf.as_ptr()) };
+            languages.push(language.to_string_lossy().into_owned());
+        }
+    }
+    unsafe { CFRelease(array) };
+
+    Some(languages.join(","))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn system_languages() -> Option<String> {
+    None
+}
+
+impl fmt::Display for Locales {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("all"),
+            Self::Languages(languages) => {
+                let languages: Vec<_> = languages.iter().map(String::as_str).collect();
+                f.write_str(&languages.join(","))
+            }
+        }
+    }
+}
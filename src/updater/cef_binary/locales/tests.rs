@@ -0,0 +1,59 @@
+use super::*;
+
+fn languages(list: &[&str]) -> Locales {
+    Locales::Languages(list.iter().map(|s| s.to_string()).collect())
+}
+
+#[test]
+fn parse() {
+    assert_eq!(Locales::parse("de_DE.UTF-8"), languages(&["de", "en"]));
+    assert_eq!(
+        Locales::parse("pt-BR, fr_CA@euro,ZH-tw"),
+        languages(&["en", "fr", "pt", "zh"])
+    );
+    assert_eq!(Locales::parse("C.UTF-8"), languages(&["en"]));
+    assert_eq!(Locales::parse(""), languages(&["en"]));
+    assert_eq!(Locales::parse(" ALL "), Locales::All);
+
+    assert_eq!(Locales::parse("de,en").to_string(), "de,en");
+    assert_eq!(Locales::parse(&Locales::All.to_string()), Locales::All);
+}
+
+#[test]
+fn contains() {
+    let de = Locales::parse("de");
+    let de_fr = Locales::parse("de,fr");
+
+    assert!(de_fr.contains(&de));
+    assert!(!de.contains(&de_fr));
+    assert!(Locales::All.contains(&de_fr));
+    assert!(!de_fr.contains(&Locales::All));
+}
+
+#[test]
+fn keeps_matching_locale_packs() {
+    let locales = Locales::parse("pt_BR.UTF-8");
+    let keeps = |path: &str| locales.keeps(Path::new(path));
+
+    assert!(keeps("Resources/locales/en-US.pak"));
+    assert!(keeps("Resources/locales/en-GB.pak"));
+    assert!(keeps("Resources/locales/pt-BR.pak"));
+    assert!(keeps("Resources/locales/pt-PT.pak"));
+    assert!(!keeps("Resources/locales/de.pak"));
+    assert!(!keeps("Resources/locales/es-419.pak"));
+
+    // macOS framework layout
+    let framework = "Release/Chromium Embedded Framework.framework/Resources";
+    assert!(keeps(&format!("{framework}/en.lproj/locale.pak")));
+    assert!(keeps(&format!("{framework}/pt_BR.lproj/locale.pak")));
+    assert!(!keeps(&format!("{framework}/zh_CN.lproj/locale.pak")));
+
+    // everything else
+    assert!(keeps("Resources/locales"));
+    assert!(keeps("Resources/resources.pak"));
+    assert!(keeps("Resources/chrome_100_percent.pak"));
+    assert!(keeps("Release/libcef.so"));
+    assert!(keeps(&format!("{framework}/icudtl.dat")));
+
+    assert!(Locales::All.keeps(Path::new("Resources/locales/de.pak")));
+}
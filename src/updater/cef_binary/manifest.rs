@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use sha1_smol::Sha1;
+use tokio_util::sync::CancellationToken;
+use tracing::*;
+
+use crate::updater::cancel::{self, Cancelled};
+
+/// How thoroughly to check the installed CEF binary at startup: `quick`
+/// (the default, sizes only), `full` (hashes every file) or `off`.
+pub const VERIFY_ENV: &str = "CEF_LOADER_VERIFY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verify {
+    Off,
+    #[default]
+    Quick,
+    Full,
+}
+
+impl Verify {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "0" => Ok(Self::Off),
+            "quick" | "" => Ok(Self::Quick),
+            "full" => Ok(Self::Full),
+            other => bail!("unknown verify level {other:?}, expected off, quick or full"),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        match env::var(VERIFY_ENV) {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|e| {
+                warn!("ignoring {VERIFY_ENV}: {:#}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub size: u64,
+    pub sha1: String,
+}
+
+/// Every regular file of an installed CEF binary, as it was right after
+/// extraction (and stripping), plus where its archive came from so missing
+/// or damaged files can be extracted again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub url: String,
+    pub file_name: String,
+    /// Keyed by `/` separated path relative to the binary directory.
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+/// `Release/libcef.so` style key for a path relative to the binary directory.
+pub fn key_of(relative_path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in relative_path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha1::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.digest().to_string())
+}
+
+impl Manifest {
+    /// Hash everything under `dir`; symlinks aren't followed or recorded.
+    pub fn build(
+        dir: &Path,
+        version: &str,
+        url: &str,
+        file_name: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative_dir) = pending.pop() {
+            let full_dir = dir.join(&relative_dir);
+            for entry in
+                fs::read_dir(&full_dir).with_context(|| format!("read_dir {full_dir:?}"))?
+            {
+                let entry = entry?;
+                let relative_path = relative_dir.join(entry.file_name());
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    pending.push(relative_path);
+                } else if file_type.is_file() {
+                    cancel::check(cancel)?;
+                    let key = key_of(&relative_path)
+                        .with_context(|| format!("non UTF-8 path {relative_path:?}"))?;
+                    let path = entry.path();
+                    let size = entry.metadata()?.len();
+                    let sha1 = hash_file(&path).with_context(|| format!("hash {path:?}"))?;
+                    files.insert(key, FileEntry { size, sha1 });
+                }
+            }
+        }
+
+        Ok(Self {
+            version: version.to_string(),
+            url: url.to_string(),
+            file_name: file_name.to_string(),
+            files,
+        })
+    }
+
+    /// `None` if there's no manifest (installs from before it existed).
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("read {path:?}")),
+        };
+        let manifest = serde_json::from_str(&text).with_context(|| format!("parse {path:?}"))?;
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("write {path:?}"))
+    }
+
+    /// Keys of the files under `dir` that are missing or don't match.
+    pub fn check(
+        &self,
+        dir: &Path,
+        verify: Verify,
+        cancel: &CancellationToken,
+    ) -> Result<BTreeSet<String>, Cancelled> {
+        let mut broken = BTreeSet::new();
+        for (key, entry) in &self.files {
+            cancel::check(cancel)?;
+            let path = dir.join(key);
+            let problem = match path.metadata() {
+                Ok(metadata) if !metadata.is_file() => Some("not a file".to_string()),
+                Ok(metadata) if metadata.len() != entry.size => {
+                    Some(format!("{} bytes, expected {}", metadata.len(), entry.size))
+                }
+                Ok(_) if verify == Verify::Full => match hash_file(&path) {
+                    Ok(sha1) if sha1 == entry.sha1 => None,
+                    Ok(sha1) => Some(format!("sha1 {sha1}, expected {}", entry.sha1)),
+                    Err(e) => Some(e.to_string()),
+                },
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            };
+
+            if let Some(problem) = problem {
+                warn!("{:?}: {}", path, problem);
+                broken.insert(key.clone());
+            }
+        }
+
+        Ok(broken)
+    }
+}
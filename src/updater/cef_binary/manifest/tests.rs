@@ -0,0 +1,100 @@
+use super::*;
+use crate::test_util::scratch_dir;
+
+fn install(name: &str) -> (PathBuf, Manifest) {
+    let dir = scratch_dir(name);
+    fs::create_dir_all(dir.join("Release")).unwrap();
+    fs::create_dir_all(dir.join("Resources/locales")).unwrap();
+    fs::write(dir.join("Release/libcef.so"), b"elf").unwrap();
+    fs::write(dir.join("Resources/locales/en-US.pak"), b"pak").unwrap();
+    fs::write(dir.join("README.txt"), b"readme").unwrap();
+
+    let manifest = Manifest::build(
+        &dir,
+        "1.0.0",
+        "https://example.com/a.tar.bz2",
+        "a.tar.bz2",
+        &CancellationToken::new(),
+    )
+    .unwrap();
+    (dir, manifest)
+}
+
+#[test]
+fn records_every_file() {
+    let (dir, manifest) = install("build");
+
+    let keys: Vec<_> = manifest.files.keys().map(String::as_str).collect();
+    assert_eq!(
+        keys,
+        [
+            "README.txt",
+            "Release/libcef.so",
+            "Resources/locales/en-US.pak"
+        ]
+    );
+    assert_eq!(
+        manifest.files["README.txt"],
+        FileEntry {
+            size: 6,
+            sha1: "f78a71af8bbf8cc2f6f313549d4da14bd3771359".to_string(),
+        }
+    );
+
+    let path = dir.join("manifest.json");
+    assert_eq!(Manifest::load(&path).unwrap(), None);
+    manifest.save(&path).unwrap();
+    assert_eq!(Manifest::load(&path).unwrap(), Some(manifest));
+}
+
+#[test]
+fn finds_missing_and_damaged_files() {
+    let (dir, manifest) = install("check");
+    let cancel = CancellationToken::new();
+    assert!(
+        manifest
+            .check(&dir, Verify::Full, &cancel)
+            .unwrap()
+            .is_empty()
+    );
+
+    fs::remove_file(dir.join("Release/libcef.so")).unwrap();
+    fs::write(dir.join("README.txt"), b"README").unwrap();
+    fs::write(dir.join("Resources/locales/en-US.pak"), b"pa").unwrap();
+
+    // same size, only a hash notices
+    let quick: Vec<_> = manifest
+        .check(&dir, Verify::Quick, &cancel)
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(quick, ["Release/libcef.so", "Resources/locales/en-US.pak"]);
+
+    let full: Vec<_> = manifest
+        .check(&dir, Verify::Full, &cancel)
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        full,
+        [
+            "README.txt",
+            "Release/libcef.so",
+            "Resources/locales/en-US.pak"
+        ]
+    );
+}
+
+#[test]
+fn parses_verify_levels() {
+    assert_eq!(Verify::parse("FULL").unwrap(), Verify::Full);
+    assert_eq!(Verify::parse("quick").unwrap(), Verify::Quick);
+    assert_eq!(Verify::parse("off").unwrap(), Verify::Off);
+    assert!(Verify::parse("sometimes").is_err());
+
+    assert_eq!(
+        key_of(Path::new("./Release/libcef.so")).unwrap(),
+        "Release/libcef.so"
+    );
+    assert_eq!(key_of(Path::new("../libcef.so")), None);
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests;
+
+use std::io;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+};
+
+/// Size of each chunk handed from the async stage to the blocking stage.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks in flight between the stages; once full, the download and
+/// decompression wait for the extractor to catch up.
+const CAPACITY: usize = 16;
+
+type Chunk = io::Result<Vec<u8>>;
+
+/// Bounded hand-off from an async reader (download + decompression) to a
+/// blocking `io::Read` consumer (the tar extractor running in
+/// `spawn_blocking`), without running a nested executor on the blocking side.
+pub fn channel() -> (Sender, ChannelReader) {
+    let (tx, rx) = mpsc::channel(CAPACITY);
+    (
+        Sender { tx },
+        ChannelReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        },
+    )
+}
+
+pub struct Sender {
+    tx: mpsc::Sender<Chunk>,
+}
+
+impl Sender {
+    /// Copy `reader` into the channel until EOF.
+    ///
+    /// A read error is forwarded to the consumer (so it stops) and returned.
+    /// If the consumer goes away first, this stops early and returns `Ok`:
+    /// the consumer's own result explains why.
+    pub async fn pump<R: AsyncRead + Unpin>(self, mut reader: R) -> io::Result<()> {
+        loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            match reader.read(&mut chunk).await {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    chunk.truncate(n);
+                    if !self.send(chunk).await {
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    self.fail(io::Error::new(e.kind(), e.to_string())).await;
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// `false` if the consumer has gone away.
+    pub async fn send(&self, chunk: Vec<u8>) -> bool {
+        self.tx.send(Ok(chunk)).await.is_ok()
+    }
+
+    /// Make the consumer's next read return `e`.
+    pub async fn fail(&self, e: io::Error) {
+        let _ = self.tx.send(Err(e)).await;
+    }
+}
+
+pub struct ChannelReader {
+    rx: mpsc::Receiver<Chunk>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl io::Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Some(Err(e)) => return Err(e),
+                // sender finished
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    io::Read,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::ReadBuf;
+
+use super::*;
+
+/// Yields `data`, then fails.
+struct FailingReader {
+    data: Option<Vec<u8>>,
+}
+
+impl AsyncRead for FailingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.data.take() {
+            Some(data) => {
+                buf.put_slice(&data);
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Ready(Err(io::Error::other("connection reset"))),
+        }
+    }
+}
+
+#[tokio::test]
+async fn pumps_everything_in_order() {
+    let data: Vec<u8> = (0..(CHUNK_SIZE * CAPACITY * 3)).map(|i| i as u8).collect();
+    let (tx, mut rx) = channel();
+
+    let consumer = tokio::task::spawn_blocking(move || {
+        let mut out = Vec::new();
+        rx.read_to_end(&mut out).map(|_| out)
+    });
+    tx.pump(data.as_slice()).await.unwrap();
+
+    assert_eq!(consumer.await.unwrap().unwrap(), data);
+}
+
+#[tokio::test]
+async fn read_error_reaches_consumer() {
+    let (tx, mut rx) = channel();
+
+    let consumer = tokio::task::spawn_blocking(move || {
+        let mut out = Vec::new();
+        rx.read_to_end(&mut out)
+    });
+    let produced = tx
+        .pump(FailingReader {
+            data: Some(b"partial".to_vec()),
+        })
+        .await;
+
+    assert_eq!(produced.unwrap_err().to_string(), "connection reset");
+    assert_eq!(
+        consumer.await.unwrap().unwrap_err().to_string(),
+        "connection reset"
+    );
+}
+
+#[tokio::test]
+async fn stops_when_consumer_goes_away() {
+    let data = vec![0u8; CHUNK_SIZE * CAPACITY * 4];
+    let (tx, mut rx) = channel();
+
+    let consumer = tokio::task::spawn_blocking(move || {
+        let mut first = [0u8; 16];
+        rx.read_exact(&mut first)
+        // dropping `rx` here simulates the extractor bailing out
+    });
+
+    tx.pump(data.as_slice()).await.unwrap();
+    consumer.await.unwrap().unwrap();
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use super::CEF_LIBRARY_NAME;
+
+/// Directory of an already installed CEF (e.g. from a distro package or the
+/// Nix store) to use instead of downloading our own. It must have the layout
+/// of `cef/cef_binary`: the library and its resources side by side.
+pub const SYSTEM_CEF_ENV: &str = "CEF_LOADER_SYSTEM_CEF";
+
+/// The system CEF's version, for packages that don't ship the `README.txt`
+/// it's normally read from.
+pub const SYSTEM_CEF_VERSION_ENV: &str = "CEF_LOADER_SYSTEM_CEF_VERSION";
+
+const README_NAME: &str = "README.txt";
+
+const README_VERSION_PREFIX: &str = "CEF Version:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemCef {
+    pub dir: PathBuf,
+    pub version: String,
+}
+
+/// `CEF Version:       134.3.8+gfe66d80+chromium-134.0.6998.166` from the
+/// `README.txt` of a CEF binary distribution.
+pub fn parse_readme_version(readme: &str) -> Option<String> {
+    readme.lines().find_map(|line| {
+        let version = line.trim().strip_prefix(README_VERSION_PREFIX)?.trim();
+        (!version.is_empty()).then(|| version.to_string())
+    })
+}
+
+impl SystemCef {
+    /// `Ok(None)` unless `SYSTEM_CEF_ENV` is set; an error if it is but
+    /// doesn't point at a usable CEF.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(dir) = env::var_os(SYSTEM_CEF_ENV).filter(|dir| !dir.is_empty()) else {
+            return Ok(None);
+        };
+        let version = env::var(SYSTEM_CEF_VERSION_ENV)
+            .ok()
+            .filter(|version| !version.trim().is_empty());
+
+        Self::open(Path::new(&dir), version.as_deref())
+            .with_context(|| format!("{SYSTEM_CEF_ENV}={dir:?}"))
+            .map(Some)
+    }
+
+    pub fn open(dir: &Path, version: Option<&str>) -> Result<Self> {
+        // absolute, since it ends up in library search paths
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("canonicalize {dir:?}"))?;
+
+        if !dir.join(CEF_LIBRARY_NAME).is_file() {
+            if dir.join("Release").join(CEF_LIBRARY_NAME).is_file() {
+                bail!(
+                    "{CEF_LIBRARY_NAME} is in Release/, point at a directory with the Release and \
+                     Resources files merged instead"
+                );
+            }
+            bail!("no {CEF_LIBRARY_NAME} in {dir:?}");
+        }
+
+        let version = match version {
+            Some(version) => version.trim().to_string(),
+            None => {
+                let readme_path = dir.join(README_NAME);
+                let readme = match fs::read_to_string(&readme_path) {
+                    Ok(readme) => readme,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => bail!(
+                        "can't tell the CEF version without {readme_path:?}, set \
+                         {SYSTEM_CEF_VERSION_ENV}"
+                    ),
+                    Err(e) => return Err(e).with_context(|| format!("read {readme_path:?}")),
+                };
+                parse_readme_version(&readme)
+                    .with_context(|| format!("no CEF version in {readme_path:?}"))?
+            }
+        };
+
+        Ok(Self { dir, version })
+    }
+
+    /// Refuse a system CEF the inner plugin wasn't built against; there's no
+    /// stable ABI between CEF builds.
+    pub fn check(&self, wanted: &str) -> Result<()> {
+        if self.version != wanted {
+            bail!(
+                "CEF Plugin needs CEF {wanted} but the system CEF in {:?} is {}",
+                self.dir,
+                self.version
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use super::*;
+use crate::test_util::scratch_dir;
+
+const VERSION: &str = "134.3.8+gfe66d80+chromium-134.0.6998.166";
+
+#[test]
+fn reads_version_from_readme() {
+    let readme = format!(
+        "Chromium Embedded Framework (CEF) Minimal Binary Distribution for Linux\n\
+         -------------------------------------------------------------------------------\n\
+         \n\
+         Date:             March 20, 2025\n\
+         \n\
+         CEF Version:      {VERSION}\n\
+         CEF URL:          https://bitbucket.org/chromiumembedded/cef.git\n"
+    );
+    assert_eq!(parse_readme_version(&readme).as_deref(), Some(VERSION));
+    assert_eq!(parse_readme_version("CEF Version:\n"), None);
+
+    let dir = scratch_dir("readme");
+    fs::write(dir.join(CEF_LIBRARY_NAME), b"lib").unwrap();
+    fs::write(dir.join(README_NAME), readme).unwrap();
+
+    let system = SystemCef::open(&dir, None).unwrap();
+    assert_eq!(system.version, VERSION);
+    assert!(system.dir.is_absolute());
+    system.check(VERSION).unwrap();
+    assert!(system.check("135.0.1+g0000000+chromium-135.0.0.0").is_err());
+
+    // the env override wins
+    let system = SystemCef::open(&dir, Some(" 1.2.3 ")).unwrap();
+    assert_eq!(system.version, "1.2.3");
+}
+
+#[test]
+fn rejects_unusable_directories() {
+    let dir = scratch_dir("unusable");
+    assert!(SystemCef::open(&dir.join("missing"), Some(VERSION)).is_err());
+
+    // a raw distribution, libraries and resources still apart
+    fs::create_dir_all(dir.join("Release")).unwrap();
+    fs::write(dir.join("Release").join(CEF_LIBRARY_NAME), b"lib").unwrap();
+    let e = SystemCef::open(&dir, Some(VERSION)).unwrap_err();
+    assert!(format!("{e:#}").contains("Release"), "{e:#}");
+
+    // no way to tell the version
+    fs::write(dir.join(CEF_LIBRARY_NAME), b"lib").unwrap();
+    let e = SystemCef::open(&dir, None).unwrap_err();
+    assert!(format!("{e:#}").contains(SYSTEM_CEF_VERSION_ENV), "{e:#}");
+}
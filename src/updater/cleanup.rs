@@ -0,0 +1,280 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tracing::*;
+
+use super::{
+    CEF_EXE_PATH, CEF_PLUGIN_PATH, LEGACY_CEF_EXE_PATH,
+    cef_binary::{
+        CEF_BINARY_PATH, CEF_BINARY_PATH_NEW, CEF_BINARY_VERSION_PATH, CEF_SYMBOLS_PATH,
+        CEF_SYMBOLS_VERSION_PATH, distribution,
+    },
+    events::{self, UpdateEvent},
+    human_bytes, rollback,
+};
+use crate::self_path::current_lib_path;
+
+const CEF_DIR_PATH: &str = "cef";
+
+/// How classicube-plugin-updater-plugin names the loader it installs into
+/// `plugins/managed/`, followed by the version.
+const MANAGED_LOADER_PREFIX: &str = "SpiralP-classicube-cef-loader-plugin-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleReason {
+    /// `-new`/`-old` sibling left by an interrupted or previous flip/flop.
+    Leftover,
+    /// Abandoned `cef_binary-new` extraction directory.
+    Staging,
+    /// Pre-rename `cef-<os>-<arch>` binary from older installs.
+    Legacy,
+    /// Version nothing loads anymore: debug symbols that aren't wanted or
+    /// don't match the installed CEF, or a managed loader we replaced.
+    Unreferenced,
+}
+
+#[derive(Debug)]
+pub struct StaleArtifact {
+    pub path: PathBuf,
+    pub reason: StaleReason,
+}
+
+#[derive(Debug, Default)]
+pub struct CleanupReport {
+    pub removed: Vec<StaleArtifact>,
+    pub reclaimed_bytes: u64,
+    pub failed: usize,
+}
+
+fn file_name_of(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .expect("path must have a UTF-8 file name")
+}
+
+fn read_marker(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// Classify the entries of `cef_dir` and the siblings of `loader_path`. Only
+/// top-level entries are considered; nothing inside `cef/cache` or the
+/// extracted CEF binary is ever touched, nor anything named in `keep`. Only
+/// names this platform installs are matched, since another game (e.g. the 32
+/// bit one, or the Windows one under Wine) may share `cef/`.
+pub fn find_stale(
+    cef_dir: &Path,
+    loader_path: Option<&Path>,
+    keep: &[OsString],
+    symbols_wanted: bool,
+) -> io::Result<Vec<StaleArtifact>> {
+    let staging_name = file_name_of(CEF_BINARY_PATH_NEW);
+    let legacy_name = file_name_of(&LEGACY_CEF_EXE_PATH);
+    let owned = [
+        file_name_of(&CEF_PLUGIN_PATH),
+        file_name_of(&CEF_EXE_PATH),
+        legacy_name,
+        file_name_of(CEF_BINARY_PATH),
+    ];
+    let symbols_names = [
+        file_name_of(CEF_SYMBOLS_PATH),
+        file_name_of(CEF_SYMBOLS_VERSION_PATH),
+    ];
+    // symbols for a CEF we no longer have are useless to a debugger
+    let symbols_unreferenced = !symbols_wanted
+        || read_marker(&cef_dir.join(file_name_of(CEF_SYMBOLS_VERSION_PATH)))
+            != read_marker(&cef_dir.join(file_name_of(CEF_BINARY_VERSION_PATH)));
+
+    let mut stale = Vec::new();
+
+    let entries = match fs::read_dir(cef_dir) {
+        Ok(entries) => Some(entries),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries.into_iter().flatten() {
+        let entry = entry?;
+        if keep.contains(&entry.file_name()) {
+            continue;
+        }
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        let reason = if name == staging_name {
+            StaleReason::Staging
+        } else if owned.iter().any(|owned| {
+            name.strip_prefix(owned)
+                .is_some_and(|suffix| suffix == "-new" || suffix == "-old")
+        }) {
+            StaleReason::Leftover
+        } else if name == legacy_name {
+            StaleReason::Legacy
+        } else if symbols_unreferenced && symbols_names.contains(&name.as_str()) {
+            StaleReason::Unreferenced
+        } else {
+            continue;
+        };
+
+        stale.push(StaleArtifact {
+            path: entry.path(),
+            reason,
+        });
+    }
+
+    if let Some(loader_path) = loader_path {
+        for suffix in ["-new", "-old"] {
+            let mut name = loader_path.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            let path = loader_path.with_file_name(name);
+            if path.symlink_metadata().is_ok() {
+                stale.push(StaleArtifact {
+                    path,
+                    reason: StaleReason::Leftover,
+                });
+            }
+        }
+
+        stale.extend(find_stale_managed_loaders(loader_path)?);
+    }
+
+    Ok(stale)
+}
+
+/// Other versions of a loader installed into `plugins/managed/` that our
+/// self-update wrote over, which ClassiCube would otherwise load alongside us.
+fn find_stale_managed_loaders(loader_path: &Path) -> io::Result<Vec<StaleArtifact>> {
+    let (Some(dir), Some(loader_name)) = (loader_path.parent(), loader_path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    if dir.file_name().is_none_or(|name| name != "managed")
+        || !loader_name
+            .to_str()
+            .is_some_and(|name| name.starts_with(MANAGED_LOADER_PREFIX))
+    {
+        return Ok(Vec::new());
+    }
+    let extension = loader_path.extension();
+
+    let mut stale = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == loader_name
+            || path.extension() != extension
+            || !entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(MANAGED_LOADER_PREFIX))
+        {
+            continue;
+        }
+
+        stale.push(StaleArtifact {
+            path,
+            reason: StaleReason::Unreferenced,
+        });
+    }
+
+    Ok(stale)
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| disk_usage(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0)
+    } else {
+        metadata.len()
+    }
+}
+
+/// Remove everything `find_stale` reports. Failures (e.g. a `-old` DLL that
+/// Windows still has mapped) are logged and counted, never fatal.
+pub fn remove_stale(stale: Vec<StaleArtifact>) -> CleanupReport {
+    let mut report = CleanupReport::default();
+
+    for artifact in stale {
+        let size = disk_usage(&artifact.path);
+        let result = if artifact.path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            fs::remove_dir_all(&artifact.path)
+        } else {
+            fs::remove_file(&artifact.path)
+        };
+
+        match result {
+            Ok(()) => {
+                debug!("removed {:?} ({:?})", artifact.path, artifact.reason);
+                report.reclaimed_bytes += size;
+                report.removed.push(artifact);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("couldn't remove {:?}: {:#}", artifact.path, e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    report
+}
+
+/// Startup garbage collection of artifacts left behind by previous updates.
+/// Must run before the updater starts writing new `-new` files.
+pub async fn run() -> Result<CleanupReport> {
+    let loader_path = match current_lib_path() {
+        Ok(path) => Some(path),
+        Err(e) => {
+            warn!("not cleaning up loader leftovers: {:#}", e);
+            None
+        }
+    };
+
+    // the previous versions of an update that hasn't loaded yet
+    let keep = rollback::kept_file_names().unwrap_or_else(|e| {
+        warn!("couldn't read the update journal: {:#}", e);
+        Vec::new()
+    });
+
+    let report = tokio::task::spawn_blocking(move || {
+        let stale = find_stale(
+            Path::new(CEF_DIR_PATH),
+            loader_path.as_deref(),
+            &keep,
+            distribution::symbols_wanted(),
+        )?;
+        Ok::<_, io::Error>(remove_stale(stale))
+    })
+    .await??;
+
+    if !report.removed.is_empty() {
+        info!(
+            "cleaned up {} stale artifacts, reclaimed {}",
+            report.removed.len(),
+            human_bytes(report.reclaimed_bytes)
+        );
+        events::emit(UpdateEvent::CleanedUp {
+            files: report.removed.len(),
+            bytes: report.reclaimed_bytes,
+        });
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,180 @@
+use std::fs;
+
+use super::*;
+use crate::{
+    test_util::scratch_dir,
+    updater::{CEF_EXE_PATH, CEF_PLUGIN_PATH},
+};
+
+fn reason_of(stale: &[StaleArtifact], name: &str) -> Option<StaleReason> {
+    stale
+        .iter()
+        .find(|artifact| artifact.path.file_name().unwrap() == name)
+        .map(|artifact| artifact.reason)
+}
+
+#[test]
+fn find_stale_classifies_cef_dir() {
+    let root = scratch_dir("classify");
+    let cef_dir = root.join("cef");
+    fs::create_dir_all(cef_dir.join("cache")).unwrap();
+    fs::create_dir_all(cef_dir.join(file_name_of(CEF_BINARY_PATH_NEW))).unwrap();
+
+    let plugin_name = file_name_of(&CEF_PLUGIN_PATH);
+    let exe_name = file_name_of(&CEF_EXE_PATH);
+    let legacy_name = file_name_of(&LEGACY_CEF_EXE_PATH);
+    for name in [
+        plugin_name,
+        exe_name,
+        legacy_name,
+        "cef_binary.txt",
+        "classicube_cef_linux_sparc.so",
+        "cef_windows_i686.exe",
+        "cef_windows_i686.exe-old",
+    ] {
+        fs::write(cef_dir.join(name), b"x").unwrap();
+    }
+    fs::write(cef_dir.join(format!("{plugin_name}-old")), b"old").unwrap();
+
+    let stale = find_stale(&cef_dir, None, &[], false).unwrap();
+
+    assert_eq!(
+        reason_of(&stale, file_name_of(CEF_BINARY_PATH_NEW)),
+        Some(StaleReason::Staging)
+    );
+    assert_eq!(
+        reason_of(&stale, &format!("{plugin_name}-old")),
+        Some(StaleReason::Leftover)
+    );
+    assert_eq!(reason_of(&stale, legacy_name), Some(StaleReason::Legacy));
+    // other platforms' games may share cef/
+    for kept in [
+        plugin_name,
+        exe_name,
+        "cef_binary.txt",
+        "cache",
+        "classicube_cef_linux_sparc.so",
+        "cef_windows_i686.exe",
+        "cef_windows_i686.exe-old",
+    ] {
+        assert_eq!(reason_of(&stale, kept), None, "{kept} must be kept");
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn remove_stale_reports_reclaimed_space() {
+    let root = scratch_dir("remove");
+    let loader_path = root.join("classicube_cef_loader.so");
+    fs::write(&loader_path, b"current").unwrap();
+    fs::write(root.join("classicube_cef_loader.so-old"), [0u8; 100]).unwrap();
+    let staging = root.join("cef").join(file_name_of(CEF_BINARY_PATH_NEW));
+    fs::create_dir_all(staging.join("locales")).unwrap();
+    fs::write(staging.join("locales").join("en-US.pak"), [0u8; 28]).unwrap();
+
+    let stale = find_stale(&root.join("cef"), Some(&loader_path), &[], false).unwrap();
+    let report = remove_stale(stale);
+
+    assert_eq!(report.removed.len(), 2);
+    assert_eq!(report.reclaimed_bytes, 128);
+    assert_eq!(report.failed, 0);
+    assert!(loader_path.is_file());
+    assert!(!staging.exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn find_stale_leaves_kept_files_alone() {
+    let root = scratch_dir("keep");
+    let cef_dir = root.join("cef");
+    fs::create_dir_all(cef_dir.join("cef_binary-old")).unwrap();
+    let plugin_old = format!("{}-old", file_name_of(&CEF_PLUGIN_PATH));
+    fs::write(cef_dir.join(&plugin_old), b"old").unwrap();
+
+    let stale = find_stale(&cef_dir, None, &[OsString::from("cef_binary-old")], false).unwrap();
+
+    assert_eq!(reason_of(&stale, "cef_binary-old"), None);
+    assert_eq!(reason_of(&stale, &plugin_old), Some(StaleReason::Leftover));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn find_stale_keeps_symbols_only_for_the_installed_cef() {
+    let root = scratch_dir("symbols");
+    let cef_dir = root.join("cef");
+    fs::create_dir_all(cef_dir.join("cef_binary_symbols")).unwrap();
+    fs::write(cef_dir.join("cef_binary.txt"), b"2").unwrap();
+    fs::write(cef_dir.join("cef_binary_symbols.txt"), b"2").unwrap();
+
+    assert!(find_stale(&cef_dir, None, &[], true).unwrap().is_empty());
+
+    let stale = find_stale(&cef_dir, None, &[], false).unwrap();
+    assert_eq!(
+        reason_of(&stale, "cef_binary_symbols"),
+        Some(StaleReason::Unreferenced)
+    );
+    assert_eq!(
+        reason_of(&stale, "cef_binary_symbols.txt"),
+        Some(StaleReason::Unreferenced)
+    );
+
+    fs::write(cef_dir.join("cef_binary.txt"), b"3").unwrap();
+    let stale = find_stale(&cef_dir, None, &[], true).unwrap();
+    assert_eq!(
+        reason_of(&stale, "cef_binary_symbols"),
+        Some(StaleReason::Unreferenced)
+    );
+    assert_eq!(reason_of(&stale, "cef_binary.txt"), None);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn find_stale_finds_replaced_managed_loaders() {
+    let root = scratch_dir("managed");
+    let managed = root.join("plugins").join("managed");
+    fs::create_dir_all(&managed).unwrap();
+    let loader_path = managed.join("SpiralP-classicube-cef-loader-plugin-v2.1.76.so");
+    for name in [
+        "SpiralP-classicube-cef-loader-plugin-v2.1.76.so",
+        "SpiralP-classicube-cef-loader-plugin-v2.1.75.so",
+        "SpiralP-classicube-cef-loader-plugin-v2.1.75.dll",
+        "SpiralP-classicube-other-plugin-v1.0.0.so",
+    ] {
+        fs::write(managed.join(name), b"x").unwrap();
+    }
+
+    let stale = find_stale(&root.join("cef"), Some(&loader_path), &[], false).unwrap();
+
+    assert_eq!(stale.len(), 1);
+    assert_eq!(
+        reason_of(&stale, "SpiralP-classicube-cef-loader-plugin-v2.1.75.so"),
+        Some(StaleReason::Unreferenced)
+    );
+
+    // a manual install is left alone
+    let stale = find_stale(
+        &root.join("cef"),
+        Some(&root.join("plugins").join("classicube_cef_loader.so")),
+        &[],
+        false,
+    )
+    .unwrap();
+    assert!(stale.is_empty());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn find_stale_tolerates_missing_cef_dir() {
+    let root = scratch_dir("missing");
+    assert!(
+        find_stale(&root.join("cef"), None, &[], false)
+            .unwrap()
+            .is_empty()
+    );
+    fs::remove_dir_all(&root).unwrap();
+}
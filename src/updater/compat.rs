@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod tests;
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use super::cef_binary::CEF_BINARY_VERSION_PATH;
+
+/// File published at the root of each `classicube-cef-plugin` release tag,
+/// next to `cef_binary_version`. Releases from before the contract existed
+/// don't have it and are treated as compatible with everything.
+pub const COMPAT_FILE_NAME: &str = "cef_compat.json";
+
+/// Where the contract of the currently installed inner plugin is kept, so
+/// `loader::plugin::try_init` can check it without network access.
+pub const COMPAT_PATH: &str = "cef/classicube-cef-plugin.compat.json";
+
+pub const LOADER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compat {
+    /// Oldest loader that can load this inner plugin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_loader_version: Option<String>,
+
+    /// Exact CEF build the inner plugin was compiled against. CEF doesn't
+    /// keep a stable ABI between builds, so anything else crashes inside
+    /// libcef. Filled in from the release's `cef_binary_version` when
+    /// installing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cef_binary_version: Option<String>,
+
+    /// The build installed in place of `cef_binary_version` because this
+    /// platform's CEF builds were discontinued before it (see
+    /// `index::Index::resolve`); the only other build that passes the check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cef_binary_substitute: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    /// Parses `2.1.99`, `v2.1.99` or `2.1.99-beta`; missing parts are 0.
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let core = s.split(['-', '+']).next().unwrap_or_default();
+
+        let mut parts = core.split('.').map(|part| {
+            part.parse::<u32>()
+                .with_context(|| format!("invalid version {s:?}"))
+        });
+        let major = parts
+            .next()
+            .with_context(|| format!("empty version {s:?}"))??;
+        let minor = parts.next().transpose()?.unwrap_or(0);
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            bail!("invalid version {s:?}");
+        }
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Compat {
+    pub fn parse(text: &str) -> Result<Self> {
+        serde_json::from_str(text).with_context(|| format!("invalid {COMPAT_FILE_NAME}"))
+    }
+
+    /// `Ok(None)` when no inner plugin contract has been installed yet.
+    pub fn load_installed() -> Result<Option<Self>> {
+        if !Path::new(COMPAT_PATH).is_file() {
+            return Ok(None);
+        }
+
+        let text =
+            fs::read_to_string(COMPAT_PATH).with_context(|| format!("read {COMPAT_PATH}"))?;
+        Ok(Some(Self::parse(&text)?))
+    }
+
+    pub fn save_installed(&self) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(COMPAT_PATH, text).with_context(|| format!("write {COMPAT_PATH}"))?;
+        Ok(())
+    }
+
+    pub fn check_loader(&self, loader_version: &str) -> Result<()> {
+        let Some(min_loader_version) = &self.min_loader_version else {
+            return Ok(());
+        };
+
+        let min = Version::parse(min_loader_version)?;
+        let ours = Version::parse(loader_version)?;
+        if ours < min {
+            bail!(
+                "CEF Plugin needs CEF Loader {min} or newer but this is {ours}; restart ClassiCube \
+                 so the updated loader is used, or update the loader manually"
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn check_cef_binary(&self, installed: Option<&str>) -> Result<()> {
+        let Some(expected) = self
+            .cef_binary_substitute
+            .as_ref()
+            .or(self.cef_binary_version.as_ref())
+        else {
+            return Ok(());
+        };
+
+        match installed {
+            Some(installed) if installed == expected => Ok(()),
+            Some(installed) => bail!(
+                "CEF Plugin was built for CEF {expected} but CEF {installed} is installed; restart \
+                 ClassiCube to let the updater fix it, or delete {CEF_BINARY_VERSION_PATH} to force a reinstall"
+            ),
+            None => bail!(
+                "CEF Plugin needs CEF {expected} but no CEF binary is installed; restart ClassiCube \
+                 to let the updater download it"
+            ),
+        }
+    }
+
+    pub fn check(&self, loader_version: &str, installed_cef_binary: Option<&str>) -> Result<()> {
+        self.check_loader(loader_version)?;
+        self.check_cef_binary(installed_cef_binary)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use super::*;
+
+#[test]
+fn version_parse() {
+    let v = |major, minor, patch| Version {
+        major,
+        minor,
+        patch,
+    };
+
+    assert_eq!(Version::parse("2.1.99").unwrap(), v(2, 1, 99));
+    assert_eq!(Version::parse("v2.1.100").unwrap(), v(2, 1, 100));
+    assert_eq!(Version::parse("3").unwrap(), v(3, 0, 0));
+    assert_eq!(Version::parse("2.2.0-beta.1").unwrap(), v(2, 2, 0));
+    assert!(Version::parse("").is_err());
+    assert!(Version::parse("2.x").is_err());
+    assert!(Version::parse("1.2.3.4").is_err());
+
+    assert!(v(2, 1, 100) > v(2, 1, 99));
+    assert!(v(2, 10, 0) > v(2, 9, 9));
+}
+
+#[test]
+fn missing_fields_are_compatible() {
+    let compat = Compat::parse("{}").unwrap();
+    assert_eq!(compat, Compat::default());
+    compat.check("0.0.1", None).unwrap();
+}
+
+#[test]
+fn check_loader_version() {
+    let compat = Compat::parse(r#"{ "min_loader_version": "2.2.0" }"#).unwrap();
+
+    compat.check_loader("2.2.0").unwrap();
+    compat.check_loader("2.10.1").unwrap();
+    let e = compat.check_loader("2.1.99").unwrap_err();
+    assert!(e.to_string().contains("2.2.0"), "{e}");
+}
+
+#[test]
+fn check_cef_binary_version() {
+    let compat = Compat {
+        min_loader_version: None,
+        cef_binary_version: Some("134.3.8+gfe66d80+chromium-134.0.6998.166".to_string()),
+        cef_binary_substitute: None,
+    };
+
+    compat
+        .check_cef_binary(Some("134.3.8+gfe66d80+chromium-134.0.6998.166"))
+        .unwrap();
+    assert!(
+        compat
+            .check_cef_binary(Some("101.0.18+g367b4a0+chromium-101.0.4951.67"))
+            .is_err()
+    );
+    assert!(compat.check_cef_binary(None).is_err());
+}
+
+#[test]
+fn check_cef_binary_substitute() {
+    let compat = Compat {
+        min_loader_version: None,
+        cef_binary_version: Some("139.0.40+g465474a+chromium-139.0.7258.139".to_string()),
+        cef_binary_substitute: Some("101.0.18+g367b4a0+chromium-101.0.4951.67".to_string()),
+    };
+
+    compat
+        .check_cef_binary(Some("101.0.18+g367b4a0+chromium-101.0.4951.67"))
+        .unwrap();
+    assert!(
+        compat
+            .check_cef_binary(Some("139.0.40+g465474a+chromium-139.0.7258.139"))
+            .is_err()
+    );
+}
+
+#[test]
+fn unknown_fields_are_ignored() {
+    let compat = Compat::parse(r#"{ "min_loader_version": "2.1.0", "future": true }"#).unwrap();
+    assert_eq!(compat.min_loader_version.as_deref(), Some("2.1.0"));
+}
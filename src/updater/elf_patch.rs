@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use tracing::*;
+
+use crate::elf::{self, Patch};
+
+/// Dynamic linker to give downloaded executables, for systems without one at
+/// the usual FHS path, e.g. on NixOS
+/// `$(cat $NIX_CC/nix-support/dynamic-linker)`.
+pub const INTERPRETER_ENV: &str = "CEF_LOADER_ELF_INTERPRETER";
+
+/// `:` separated directories downloaded binaries should find their system
+/// libraries (nss, gtk, X11, ...) in, ahead of anything they already search.
+pub const RUNPATH_ENV: &str = "CEF_LOADER_ELF_RUNPATH";
+
+/// Rewrites downloaded ELF binaries after they're written so they run on
+/// non-FHS systems.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfPatch {
+    pub interpreter: Option<String>,
+    pub runpath: Option<String>,
+}
+
+impl ElfPatch {
+    /// `None` unless `INTERPRETER_ENV` or `RUNPATH_ENV` is set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let patch = Self {
+            interpreter: var(INTERPRETER_ENV),
+            runpath: var(RUNPATH_ENV),
+        };
+        (patch.interpreter.is_some() || patch.runpath.is_some()).then_some(patch)
+    }
+
+    /// Patch `path` in place if it's an ELF file; `true` if it was
+    /// rewritten.
+    pub fn apply(&self, path: &Path) -> Result<bool> {
+        if !is_elf(path).with_context(|| format!("read {path:?}"))? {
+            return Ok(false);
+        }
+
+        let patched = elf::patch(
+            path,
+            Patch {
+                interpreter: self.interpreter.as_deref(),
+                runpath: self.runpath.as_deref(),
+            },
+        )
+        .with_context(|| format!("patch {path:?}"))?;
+        if patched {
+            debug!("patched {:?}", path);
+        }
+        Ok(patched)
+    }
+}
+
+fn is_elf(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; 4];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == elf::MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
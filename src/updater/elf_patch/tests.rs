@@ -0,0 +1,26 @@
+use std::fs;
+
+use super::*;
+use crate::test_util::scratch_dir;
+
+#[test]
+fn skips_files_that_arent_elf() {
+    let dir = scratch_dir("not-elf");
+    let patch = ElfPatch {
+        interpreter: Some("/nix/store/glibc/lib/ld-linux-x86-64.so.2".to_string()),
+        runpath: Some("/nix/store/nss/lib".to_string()),
+    };
+
+    for (name, contents) in [
+        ("icudtl.dat", &b"icudt74l data"[..]),
+        ("empty.pak", &b""[..]),
+        ("short", &b"\x7fE"[..]),
+    ] {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        assert!(!patch.apply(&path).unwrap(), "{name}");
+        assert_eq!(fs::read(&path).unwrap(), contents);
+    }
+
+    assert!(patch.apply(&dir.join("missing")).is_err());
+}
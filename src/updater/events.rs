@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests;
+
+use std::sync::Mutex;
+
+use tokio::sync::mpsc;
+use tracing::*;
+
+use crate::async_manager;
+
+/// One unbounded queue per subscriber, so none of them can miss an
+/// `Installed` or `Failed` by falling behind. Progress events are throttled
+/// by `progress::Throttle`, which keeps the queues short.
+static SUBSCRIBERS: Mutex<Vec<mpsc::UnboundedSender<UpdateEvent>>> = Mutex::new(Vec::new());
+
+/// Everything the updater has to say, in order, per `component` (an asset
+/// name, "CEF Binary", ...). The chat/status line UI, the log and tests are
+/// all just subscribers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateEvent {
+    Checking {
+        component: String,
+    },
+    UpToDate {
+        component: String,
+    },
+    Updating {
+        component: String,
+        version: String,
+    },
+    Downloading {
+        component: String,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// The transfer ended, successfully or not.
+    Downloaded {
+        component: String,
+    },
+    /// Unpacking started; while downloading this happens as the bytes arrive.
+    Decompressing {
+        component: String,
+    },
+    Extracting {
+        component: String,
+        files: usize,
+    },
+    /// Removing debug info from `file`, which can take a while for libcef.
+    Stripping {
+        component: String,
+        file: String,
+    },
+    /// Everything is unpacked; checking and moving it into place.
+    Finalizing {
+        component: String,
+    },
+    Verifying {
+        component: String,
+    },
+    Installed {
+        component: String,
+        version: String,
+    },
+    /// `files` of an installed component are missing or damaged and are
+    /// being extracted again.
+    Repairing {
+        component: String,
+        files: usize,
+    },
+    Repaired {
+        component: String,
+        files: usize,
+    },
+    Skipped {
+        component: String,
+        reason: String,
+    },
+    Failed {
+        component: String,
+        error: String,
+    },
+    CleanedUp {
+        files: usize,
+        bytes: u64,
+    },
+    RestartRequired {
+        component: String,
+        version: String,
+    },
+}
+
+pub fn emit(event: UpdateEvent) {
+    // sending under the lock keeps every subscriber's order the same; no
+    // receivers is fine, and dropped ones are forgotten
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+}
+
+/// Everything emitted from now on, until the receiver is dropped.
+pub fn subscribe() -> mpsc::UnboundedReceiver<UpdateEvent> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    SUBSCRIBERS.lock().unwrap().push(tx);
+    rx
+}
+
+/// Subscriber that mirrors every event into the log.
+pub fn start_logging() {
+    let mut rx = subscribe();
+    async_manager::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if let UpdateEvent::Downloading { .. } = event {
+                trace!("{:?}", event);
+            } else {
+                debug!("{:?}", event);
+            }
+        }
+    });
+}
@@ -0,0 +1,134 @@
+use super::*;
+use crate::updater::progress;
+
+/// Test subscriber: collects everything emitted for one component. The
+/// channel is global and tests run in parallel, so filter by name.
+struct Recorder {
+    rx: mpsc::UnboundedReceiver<UpdateEvent>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        Self { rx: subscribe() }
+    }
+
+    fn events_for(&mut self, component: &str) -> Vec<UpdateEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.rx.try_recv() {
+            events.push(event);
+        }
+
+        events
+            .into_iter()
+            .filter(|event| match event {
+                UpdateEvent::Checking { component: c }
+                | UpdateEvent::UpToDate { component: c }
+                | UpdateEvent::Updating { component: c, .. }
+                | UpdateEvent::Downloading { component: c, .. }
+                | UpdateEvent::Downloaded { component: c }
+                | UpdateEvent::Decompressing { component: c }
+                | UpdateEvent::Extracting { component: c, .. }
+                | UpdateEvent::Stripping { component: c, .. }
+                | UpdateEvent::Finalizing { component: c }
+                | UpdateEvent::Verifying { component: c }
+                | UpdateEvent::Installed { component: c, .. }
+                | UpdateEvent::Repairing { component: c, .. }
+                | UpdateEvent::Repaired { component: c, .. }
+                | UpdateEvent::Skipped { component: c, .. }
+                | UpdateEvent::Failed { component: c, .. }
+                | UpdateEvent::RestartRequired { component: c, .. } => c == component,
+                UpdateEvent::CleanedUp { .. } => false,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn emit_reaches_subscribers_in_order() {
+    let mut recorder = Recorder::new();
+
+    emit(UpdateEvent::Checking {
+        component: "emit-test".to_string(),
+    });
+    emit(UpdateEvent::Installed {
+        component: "emit-test".to_string(),
+        version: "v1".to_string(),
+    });
+
+    assert_eq!(
+        recorder.events_for("emit-test"),
+        vec![
+            UpdateEvent::Checking {
+                component: "emit-test".to_string()
+            },
+            UpdateEvent::Installed {
+                component: "emit-test".to_string(),
+                version: "v1".to_string()
+            },
+        ]
+    );
+}
+
+/// A burst bigger than any channel capacity must not push out the events
+/// that end an update.
+#[test]
+fn slow_subscribers_keep_every_event() {
+    let mut recorder = Recorder::new();
+
+    for files in 1..=10_000 {
+        emit(UpdateEvent::Extracting {
+            component: "burst-test".to_string(),
+            files,
+        });
+    }
+    emit(UpdateEvent::Installed {
+        component: "burst-test".to_string(),
+        version: "v1".to_string(),
+    });
+
+    let events = recorder.events_for("burst-test");
+    assert_eq!(events.len(), 10_001);
+    assert_eq!(
+        events.last(),
+        Some(&UpdateEvent::Installed {
+            component: "burst-test".to_string(),
+            version: "v1".to_string()
+        })
+    );
+}
+
+#[tokio::test]
+async fn transfer_reports_first_and_last_progress() {
+    let mut recorder = Recorder::new();
+
+    {
+        let transfer = progress::acquire_slot()
+            .await
+            .start("transfer-test", Some(300));
+        transfer.add(100);
+        transfer.add(200);
+    }
+
+    let events = recorder.events_for("transfer-test");
+    assert_eq!(
+        events.first(),
+        Some(&UpdateEvent::Downloading {
+            component: "transfer-test".to_string(),
+            bytes: 0,
+            total: Some(300)
+        })
+    );
+    assert_eq!(
+        &events[events.len() - 2..],
+        &[
+            UpdateEvent::Downloading {
+                component: "transfer-test".to_string(),
+                bytes: 300,
+                total: Some(300)
+            },
+            UpdateEvent::Downloaded {
+                component: "transfer-test".to_string()
+            },
+        ]
+    );
+}
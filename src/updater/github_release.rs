@@ -1,17 +1,27 @@
 use std::{
     env,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use anyhow::{Context, Error, Result, bail};
-use classicube_helpers::color;
-use futures::stream::TryStreamExt;
-use reqwest::header::{AUTHORIZATION, HeaderValue};
+use futures::{future, stream::TryStreamExt};
+use reqwest::{
+    StatusCode,
+    header::{AUTHORIZATION, HeaderValue},
+};
 use serde::Deserialize;
 use tokio::{fs, io};
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
-use crate::{print_async, updater::make_client};
+#[cfg(target_os = "linux")]
+use crate::updater::elf_patch::ElfPatch;
+use crate::updater::{
+    cancel::{self, Cancellable},
+    events::{self, UpdateEvent},
+    make_client, progress, rollback,
+};
 
 const VERSIONS_DIR_PATH: &str = "cef";
 
@@ -85,6 +95,9 @@ pub struct GitHubReleaseChecker {
     repo: String,
     asset_specs: Vec<AssetSpec>,
     release: GitHubRelease,
+    rollback: bool,
+    /// Set once any asset of this release is in place.
+    installed_any: AtomicBool,
 }
 
 impl GitHubReleaseChecker {
@@ -104,9 +117,28 @@ impl GitHubReleaseChecker {
             repo,
             asset_specs: asset_specs.into(),
             release,
+            rollback: false,
+            installed_any: AtomicBool::new(false),
         })
     }
 
+    /// Keep replaced assets as `-old` in the rollback journal until the new
+    /// ones have loaded, see `rollback`.
+    pub fn with_rollback(mut self) -> Self {
+        self.rollback = true;
+        self
+    }
+
+    pub fn tag_name(&self) -> &str {
+        &self.release.tag_name
+    }
+
+    /// Whether `update` put any of the release's assets in place, even if
+    /// it failed afterwards.
+    pub fn installed_any(&self) -> bool {
+        self.installed_any.load(Ordering::SeqCst)
+    }
+
     fn version_path(&self) -> PathBuf {
         let versions_dir = Path::new(VERSIONS_DIR_PATH);
         versions_dir.join(format!("{}.txt", self.repo))
@@ -139,22 +171,16 @@ impl GitHubReleaseChecker {
         }
     }
 
-    pub async fn update(&self) -> Result<bool> {
-        debug!("checking {:?}", self.name);
+    pub async fn update(&self, cancel: &CancellationToken) -> Result<bool> {
+        events::emit(UpdateEvent::Checking {
+            component: self.name.clone(),
+        });
 
-        // delete "-old" files
-        // and check if we are missing any assets
+        // check if we are missing any assets
+        // ("-old" files are removed by the startup cleanup pass)
         let mut missing_asset = false;
         for spec in &self.asset_specs {
             let wanted_path = &spec.dest_path;
-            let old_path = old_path_for(wanted_path);
-
-            if let Err(e) = fs::remove_file(&old_path).await {
-                // don't show error
-                if e.kind() != io::ErrorKind::NotFound {
-                    warn!("couldn't remove {:?}: {:#?}", &old_path, e);
-                }
-            }
 
             if !wanted_path.exists() {
                 debug!("missing {:?}", wanted_path);
@@ -170,7 +196,12 @@ impl GitHubReleaseChecker {
                 .unwrap_or(true);
 
         if needs_update {
-            self.update_assets(&self.release).await?;
+            events::emit(UpdateEvent::Updating {
+                component: self.name.clone(),
+                version: self.release.tag_name.clone(),
+            });
+
+            self.update_assets(&self.release, cancel).await?;
 
             {
                 // mark that we updated
@@ -179,72 +210,109 @@ impl GitHubReleaseChecker {
 
             Ok(true)
         } else {
-            debug!("{} up to date", self.name);
+            events::emit(UpdateEvent::UpToDate {
+                component: self.name.clone(),
+            });
             Ok(false)
         }
     }
 
-    async fn update_assets(&self, release: &GitHubRelease) -> Result<()> {
-        for spec in &self.asset_specs {
-            let asset = release
-                .assets
-                .iter()
-                .find(|asset| asset.name == spec.asset_name)
-                .with_context(|| format!("couldn't find asset {}", spec.asset_name))?;
-
-            let wanted_path = spec.dest_path.clone();
-            let new_path = new_path_for(&wanted_path);
-            let old_path = old_path_for(&wanted_path);
+    async fn update_assets(
+        &self,
+        release: &GitHubRelease,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        // concurrency is bounded by `progress::acquire_slot`
+        let siblings = cancel.child_token();
+        let results =
+            future::join_all(self.asset_specs.iter().map(|spec| {
+                cancel::or_cancel(&siblings, self.update_asset(release, spec, &siblings))
+            }))
+            .await;
+        cancel::all(results)?;
+
+        Ok(())
+    }
+
+    async fn update_asset(
+        &self,
+        release: &GitHubRelease,
+        spec: &AssetSpec,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let asset = release
+            .assets
+            .iter()
+            .find(|asset| asset.name == spec.asset_name)
+            .with_context(|| format!("couldn't find asset {}", spec.asset_name))?;
+
+        let wanted_path = spec.dest_path.clone();
+        let new_path = new_path_for(&wanted_path);
+        let old_path = old_path_for(&wanted_path);
+        let downloaded = async {
+            download_to(asset, &new_path, cancel).await?;
+            patch_binary(&new_path).await
+        };
+        if let Err(e) = downloaded.await {
+            // half a binary is no use to anyone, cancelled or not
+            if let Err(remove_error) = fs::remove_file(&new_path).await
+                && remove_error.kind() != io::ErrorKind::NotFound
             {
-                let mut f = fs::File::create(&new_path).await?;
-
-                let mut stream = tokio_util::io::StreamReader::new(
-                    make_client()
-                        .get(&asset.browser_download_url)
-                        .send()
-                        .await?
-                        .error_for_status()?
-                        .bytes_stream()
-                        .map_err(io::Error::other),
-                );
-
-                io::copy(&mut stream, &mut f).await?;
+                warn!("couldn't remove {:?}: {}", new_path, remove_error);
             }
+            return Err(e);
+        }
 
-            if wanted_path.is_file() {
-                // we need to flip/flop files
-
-                // try to rename current loaded to -old
-                if let Err(e) = fs::rename(&wanted_path, &old_path).await {
-                    // if we can't rename to -old, it's probably still loaded
-                    // and we're updating a second time,
-                    // so try to delete current file which is probably not loaded
-                    if let Err(e2) = fs::remove_file(&wanted_path).await {
-                        bail!("failed to rename current file: {} and {}", e, e2);
-                    } else {
-                        debug!("deleted {:?} ok", &wanted_path);
-                    }
+        if wanted_path.is_file() {
+            // we need to flip/flop files
+
+            if self.rollback {
+                rollback::record_markers(&[self.version_path()])?;
+                rollback::record_replaced(&wanted_path, &old_path)?;
+            }
+
+            // try to rename current loaded to -old
+            if let Err(e) = fs::rename(&wanted_path, &old_path).await {
+                // if we can't rename to -old, it's probably still loaded
+                // and we're updating a second time,
+                // so try to delete current file which is probably not loaded
+                if let Err(e2) = fs::remove_file(&wanted_path).await {
+                    bail!("failed to rename current file: {} and {}", e, e2);
                 } else {
-                    debug!("renamed {:?} -> {:?} ok", &wanted_path, &old_path);
+                    debug!("deleted {:?} ok", &wanted_path);
                 }
+            } else {
+                debug!("renamed {:?} -> {:?} ok", &wanted_path, &old_path);
             }
+        }
 
-            // rename downloaded to wanted_path
-            fs::rename(&new_path, &wanted_path).await?;
+        // rename downloaded to wanted_path
+        fs::rename(&new_path, &wanted_path).await?;
+        self.installed_any.store(true, Ordering::SeqCst);
 
-            print_async(format!(
-                "{}Updated to {}{} {}",
-                color::GOLD,
-                color::GREEN,
-                asset.name,
-                release.tag_name
-            ))
-            .await;
-        }
+        events::emit(UpdateEvent::Installed {
+            component: asset.name.clone(),
+            version: release.tag_name.clone(),
+        });
 
         Ok(())
     }
 
+    /// Like `get_file`, but `Ok(None)` if the file doesn't exist at this tag.
+    pub async fn get_optional_file(&self, file_path: &str) -> Result<Option<String>> {
+        match self.get_file(file_path).await {
+            Ok(text) => Ok(Some(text)),
+            Err(e)
+                if e.downcast_ref::<reqwest::Error>()
+                    .and_then(reqwest::Error::status)
+                    == Some(StatusCode::NOT_FOUND) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn get_file(&self, file_path: &str) -> Result<String> {
         let owner = &self.owner;
         let repo = &self.repo;
@@ -264,6 +332,53 @@ impl GitHubReleaseChecker {
     }
 }
 
+async fn download_to(
+    asset: &GitHubReleaseAsset,
+    path: &Path,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut f = fs::File::create(path).await?;
+
+    let slot = tokio::select! {
+        slot = progress::acquire_slot() => slot,
+        () = cancel.cancelled() => return Err(cancel::Cancelled.into()),
+    };
+    let response = make_client()
+        .get(&asset.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let transfer = slot.start(&asset.name, response.content_length());
+
+    let stream = tokio_util::io::StreamReader::new(
+        response
+            .bytes_stream()
+            .inspect_ok(|bytes| transfer.add(bytes.len()))
+            .map_err(io::Error::other),
+    );
+    let mut stream = Cancellable::new(stream, cancel.clone());
+
+    io::copy(&mut stream, &mut f).await?;
+
+    Ok(())
+}
+
+/// See `ElfPatch`; the plugin's exe and libraries need it as much as CEF's.
+#[cfg(target_os = "linux")]
+async fn patch_binary(path: &Path) -> Result<()> {
+    let Some(patch) = ElfPatch::from_env() else {
+        return Ok(());
+    };
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || patch.apply(&path)).await??;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn patch_binary(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GitHubRelease {
     /// error message
@@ -1,12 +1,33 @@
+pub mod cancel;
 pub mod cef_binary;
+pub mod cleanup;
+pub mod compat;
+#[cfg(target_os = "linux")]
+pub mod elf_patch;
+pub mod events;
 pub mod github_release;
-
-use std::{path::Path, time::Duration};
+pub mod platform;
+pub mod progress;
+pub mod rollback;
+pub mod ui;
+
+use std::{
+    env,
+    path::Path,
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
 use anyhow::Result;
+use cef_binary::{distribution::Distribution, system::SystemCef};
+use compat::Compat;
+use events::UpdateEvent;
 use github_release::{AssetSpec, GitHubReleaseChecker};
-use tokio::{fs, io};
-use tracing::warn;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 
 use crate::self_path::current_lib_path;
 
@@ -21,119 +42,81 @@ pub fn make_client() -> reqwest::Client {
         .unwrap()
 }
 
-// windows 64 bit
-
-#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_windows_x86_64.dll";
-
-#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_PATH: &str = "cef/classicube_cef_windows_x86_64.dll";
-
-#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_windows_x86_64.exe";
-
-#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-windows-x86_64.exe";
-
-// windows 32 bit
-
-#[cfg(all(target_os = "windows", target_arch = "x86"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_windows_i686.dll";
-
-#[cfg(all(target_os = "windows", target_arch = "x86"))]
-pub const CEF_PLUGIN_PATH: &str = "cef/classicube_cef_windows_i686.dll";
-
-#[cfg(all(target_os = "windows", target_arch = "x86"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_windows_i686.exe";
-
-#[cfg(all(target_os = "windows", target_arch = "x86"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-windows-i686.exe";
-
-// linux 64 bit
-
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_linux_x86_64.so";
-
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_linux_x86_64.so";
-
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_linux_x86_64";
-
-#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-linux-x86_64";
-
-// linux 32 bit
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
 
-#[cfg(all(target_os = "linux", target_arch = "x86"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_linux_i686.so";
-
-#[cfg(all(target_os = "linux", target_arch = "x86"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_linux_i686.so";
-
-#[cfg(all(target_os = "linux", target_arch = "x86"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_linux_i686";
-
-#[cfg(all(target_os = "linux", target_arch = "x86"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-linux-i686";
-
-// linux armhf
-
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_linux_armhf.so";
-
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_linux_armhf.so";
-
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_linux_armhf";
-
-#[cfg(all(target_os = "linux", target_arch = "arm"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-linux-armhf";
-
-// linux aarch64
-
-#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_linux_aarch64.so";
-
-#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_linux_aarch64.so";
-
-#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_linux_aarch64";
-
-#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-linux-aarch64";
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
 
-// macos 64 bit
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
 
-#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_macos_x86_64.dylib";
+/// Whether an on/off `CEF_LOADER_*` variable is on: `1` or `true`, in any
+/// case.
+pub fn env_flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| {
+        let value = value.trim();
+        value == "1" || value.eq_ignore_ascii_case("true")
+    })
+}
 
-#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_macos_x86_64.dylib";
+pub static CEF_PLUGIN_LOADER_PATH: LazyLock<String> =
+    LazyLock::new(|| platform::CURRENT.cef_plugin_loader_path());
 
-#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_macos_x86_64";
+pub static CEF_PLUGIN_PATH: LazyLock<String> =
+    LazyLock::new(|| platform::CURRENT.cef_plugin_path());
 
-#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-macos-x86_64";
+pub static CEF_EXE_PATH: LazyLock<String> = LazyLock::new(|| platform::CURRENT.cef_exe_path());
 
-// macos aarch64
+static LEGACY_CEF_EXE_PATH: LazyLock<String> =
+    LazyLock::new(|| platform::CURRENT.legacy_cef_exe_path());
 
-#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-pub const CEF_PLUGIN_LOADER_PATH: &str = "plugins/classicube_cef_loader_macos_aarch64.dylib";
+/// Set once this session has replaced the loader binary on disk. The process
+/// keeps running the old loader code until ClassiCube is restarted.
+static LOADER_RESTART_REQUIRED: AtomicBool = AtomicBool::new(false);
 
-#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-pub const CEF_PLUGIN_PATH: &str = "./cef/classicube_cef_macos_aarch64.dylib";
+pub fn loader_restart_required() -> bool {
+    LOADER_RESTART_REQUIRED.load(Ordering::SeqCst)
+}
 
-#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-pub const CEF_EXE_PATH: &str = "cef/cef_macos_aarch64";
+/// Stops with `cancel::Cancelled` once `cancel` is cancelled, leaving no
+/// temporary files behind.
+pub async fn update_plugins(cancel: &CancellationToken) -> Result<()> {
+    // the loader, the inner plugin and the CEF binary are independent downloads,
+    // so run them side by side; `progress` bounds how many transfer at once
+    let siblings = cancel.child_token();
+    let (loader, plugin) = tokio::join!(
+        cancel::or_cancel(&siblings, update_loader(&siblings)),
+        cancel::or_cancel(&siblings, update_cef_plugin(&siblings)),
+    );
+    let (loader_tag_name, ()) = cancel::both(loader, plugin)?;
+
+    if let Some(tag_name) = loader_tag_name {
+        // A loaded cdylib can't be swapped in-process, so the old loader stays
+        // in charge until restart. We still finish updating and loading the
+        // inner plugin: it only talks to us through `IGameComponent`, and
+        // leaving the player without CEF until a restart would be worse.
+        info!("loader updated to {tag_name}, restart required");
+        LOADER_RESTART_REQUIRED.store(true, Ordering::SeqCst);
+        events::emit(UpdateEvent::RestartRequired {
+            component: "CEF Loader".to_string(),
+            version: tag_name,
+        });
+    }
 
-#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-const LEGACY_CEF_EXE_PATH: &str = "cef/cef-macos-aarch64";
+    Ok(())
+}
 
-pub async fn update_plugins() -> Result<()> {
+/// Returns the new release's tag name if the loader replaced its own binary.
+async fn update_loader(cancel: &CancellationToken) -> Result<Option<String>> {
     // Self-update: rewrite whatever file ClassiCube actually `dlopen`ed for us,
     // not a hard-coded path. That keeps a single loaded copy whether we live at
     // `plugins/classicube_cef_loader_*.so` (manual install) or
@@ -142,24 +125,22 @@ pub async fn update_plugins() -> Result<()> {
     // release is still the OS-suffixed canonical filename.
     match current_lib_path() {
         Ok(dest_path) => {
-            let asset_name = Path::new(CEF_PLUGIN_LOADER_PATH)
+            let asset_name = Path::new(CEF_PLUGIN_LOADER_PATH.as_str())
                 .file_name()
                 .and_then(|n| n.to_str())
                 .expect("CEF_PLUGIN_LOADER_PATH must have a UTF-8 file name")
                 .to_string();
 
-            let cef_loader_plugin_updated = GitHubReleaseChecker::create(
+            let cef_loader_plugin_release = GitHubReleaseChecker::create(
                 "CEF Loader Plugin",
                 "SpiralP",
                 "classicube-cef-loader-plugin",
                 vec![AssetSpec::new(asset_name, dest_path)],
             )
-            .await?
-            .update()
             .await?;
 
-            if cef_loader_plugin_updated {
-                // TODO should we break if cef loader plugin updated?
+            if cef_loader_plugin_release.update(cancel).await? {
+                return Ok(Some(cef_loader_plugin_release.tag_name().to_string()));
             }
         }
         Err(e) => {
@@ -167,36 +148,157 @@ pub async fn update_plugins() -> Result<()> {
         }
     }
 
+    Ok(None)
+}
+
+/// Tie the compat file and release to an update that replaced something, so
+/// rolling it back restores them too.
+fn record_pending_release(tag_name: &str) -> Result<()> {
+    if rollback::pending()?.is_some() {
+        rollback::record_markers(&[compat::COMPAT_PATH])?;
+        rollback::record_release(tag_name)?;
+    }
+    Ok(())
+}
+
+/// Record what the plugin `release` installed expects once `result` is in,
+/// even when it (or the CEF binary beside it) failed after some of the plugin
+/// landed: `try_init` then refuses the new plugin with the old CEF, and a
+/// crash loading it still rolls back and rejects the release.
+fn save_compat<T>(
+    release: &GitHubReleaseChecker,
+    result: &Result<T>,
+    compat: &Compat,
+) -> Result<()> {
+    if result.is_ok() || release.installed_any() {
+        record_pending_release(release.tag_name())?;
+        compat.save_installed()?;
+    }
+    Ok(())
+}
+
+async fn update_cef_plugin(cancel: &CancellationToken) -> Result<()> {
+    // going over the previous version now would lose what we'd roll back to
+    if rollback::pending()?.is_some() {
+        debug!("last update hasn't loaded yet, not updating CEF Plugin");
+        return Ok(());
+    }
+
     let cef_plugin_release = GitHubReleaseChecker::create(
         "CEF Plugin",
         "SpiralP",
         "classicube-cef-plugin",
-        vec![CEF_PLUGIN_PATH.into(), CEF_EXE_PATH.into()],
+        vec![
+            CEF_PLUGIN_PATH.as_str().into(),
+            CEF_EXE_PATH.as_str().into(),
+        ],
     )
-    .await?;
+    .await?
+    .with_rollback();
+
+    if rollback::rejected().as_deref() == Some(cef_plugin_release.tag_name()) {
+        warn!(
+            "not updating CEF Plugin to {}: it was rolled back",
+            cef_plugin_release.tag_name()
+        );
+        events::emit(UpdateEvent::Skipped {
+            component: format!("CEF Plugin {}", cef_plugin_release.tag_name()),
+            reason: format!(
+                "it failed to load last time (delete {} to retry)",
+                rollback::REJECTED_PATH
+            ),
+        });
+        return Ok(());
+    }
 
-    let cef_binary_version = if cfg!(all(target_os = "linux", target_arch = "x86")) {
-        // Linux x86 32-bit builds are discontinued after version 101
-        // https://cef-builds.spotifycdn.com/index.html#linux32
-        "101.0.18+g367b4a0+chromium-101.0.4951.67".to_string()
-    } else {
-        cef_plugin_release
-            .get_file("cef_binary_version")
-            .await?
-            .trim()
-            .to_string()
+    let mut compat = match cef_plugin_release
+        .get_optional_file(compat::COMPAT_FILE_NAME)
+        .await?
+    {
+        Some(text) => Compat::parse(&text)?,
+        None => Compat::default(),
     };
 
-    cef_plugin_release.update().await?;
+    // keep the installed (compatible) plugin rather than installing one this
+    // loader can't drive; once the loader self-update lands and the game is
+    // restarted, the next check installs it
+    if let Err(e) = compat.check_loader(compat::LOADER_VERSION) {
+        warn!("not updating CEF Plugin: {:#}", e);
+        events::emit(UpdateEvent::Skipped {
+            component: format!("CEF Plugin {}", cef_plugin_release.tag_name()),
+            reason: e.to_string(),
+        });
+        return Ok(());
+    }
 
-    // clean up the pre-rename `cef-<os>-<arch>` binary left by older installs
-    if let Err(e) = fs::remove_file(LEGACY_CEF_EXE_PATH).await
-        && e.kind() != io::ErrorKind::NotFound
-    {
-        warn!("couldn't remove legacy {LEGACY_CEF_EXE_PATH}: {e:#}");
+    let wanted_cef_binary_version = cef_plugin_release
+        .get_file("cef_binary_version")
+        .await?
+        .trim()
+        .to_string();
+    // a system CEF is never updated by us, only checked against what the
+    // plugin was built for
+    if let Some(system_cef) = SystemCef::from_env()? {
+        if let Err(e) = system_cef.check(&wanted_cef_binary_version) {
+            warn!("not updating CEF Plugin: {:#}", e);
+            events::emit(UpdateEvent::Skipped {
+                component: format!("CEF Plugin {}", cef_plugin_release.tag_name()),
+                reason: e.to_string(),
+            });
+            return Ok(());
+        }
+        debug!(
+            "using the system CEF {} in {:?}",
+            system_cef.version, system_cef.dir
+        );
+
+        compat.cef_binary_version = Some(system_cef.version);
+        compat.cef_binary_substitute = None;
+        let result = cef_plugin_release.update(cancel).await;
+        save_compat(&cef_plugin_release, &result, &compat)?;
+        result?;
+        return Ok(());
     }
 
-    cef_binary::update(&cef_binary_version).await?;
+    let distribution = Distribution::from_env();
+    // a platform whose builds were discontinued keeps the one it was given
+    // in place of this version
+    let installed_substitute = match Compat::load_installed() {
+        Ok(installed) => installed
+            .filter(|installed| {
+                installed.cef_binary_version.as_deref() == Some(wanted_cef_binary_version.as_str())
+            })
+            .and_then(|installed| installed.cef_binary_substitute),
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    };
+
+    // start the ~100 MB CEF archive while the plugin assets are still transferring
+    let siblings = cancel.child_token();
+    let (plugin, cef_build) = tokio::join!(
+        cancel::or_cancel(&siblings, cef_plugin_release.update(&siblings)),
+        cancel::or_cancel(
+            &siblings,
+            cef_binary::update(
+                installed_substitute
+                    .as_deref()
+                    .unwrap_or(&wanted_cef_binary_version),
+                distribution,
+                || cef_binary::index::resolve(&wanted_cef_binary_version, distribution),
+                &siblings,
+            )
+        ),
+    );
+
+    compat.cef_binary_version = Some(wanted_cef_binary_version);
+    compat.cef_binary_substitute = match &cef_build {
+        Ok(Some(cef_build)) => cef_build.substitutes.clone(),
+        _ => installed_substitute,
+    };
+    save_compat(&cef_plugin_release, &plugin, &compat)?;
+    cancel::both(plugin, cef_build)?;
 
     Ok(())
 }
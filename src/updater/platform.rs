@@ -0,0 +1,221 @@
+#[cfg(test)]
+mod tests;
+
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Windows,
+    Linux,
+    Mac,
+}
+
+impl Os {
+    /// As in `std::env::consts::OS` and our release asset names.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Windows => "windows",
+            Self::Linux => "linux",
+            Self::Mac => "macos",
+        }
+    }
+
+    const fn library_extension(self) -> &'static str {
+        match self {
+            Self::Windows => "dll",
+            Self::Linux => "so",
+            Self::Mac => "dylib",
+        }
+    }
+
+    const fn exe_suffix(self) -> &'static str {
+        match self {
+            Self::Windows => ".exe",
+            Self::Linux | Self::Mac => "",
+        }
+    }
+}
+
+/// Everything that differs between the targets we ship for; all the file
+/// names below are derived from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    /// As in `std::env::consts::ARCH`.
+    pub arch: &'static str,
+    /// As in our release asset names.
+    pub asset_arch: &'static str,
+    /// As in the CEF builds index.
+    pub cef_arch: &'static str,
+}
+
+pub const PLATFORMS: &[Platform] = &[
+    Platform {
+        os: Os::Windows,
+        arch: "x86_64",
+        asset_arch: "x86_64",
+        cef_arch: "windows64",
+    },
+    Platform {
+        os: Os::Windows,
+        arch: "x86",
+        asset_arch: "i686",
+        cef_arch: "windows32",
+    },
+    Platform {
+        os: Os::Linux,
+        arch: "x86_64",
+        asset_arch: "x86_64",
+        cef_arch: "linux64",
+    },
+    Platform {
+        os: Os::Linux,
+        arch: "x86",
+        asset_arch: "i686",
+        cef_arch: "linux32",
+    },
+    Platform {
+        os: Os::Linux,
+        arch: "arm",
+        asset_arch: "armhf",
+        cef_arch: "linuxarm",
+    },
+    Platform {
+        os: Os::Linux,
+        arch: "aarch64",
+        asset_arch: "aarch64",
+        cef_arch: "linuxarm64",
+    },
+    Platform {
+        os: Os::Mac,
+        arch: "x86_64",
+        asset_arch: "x86_64",
+        cef_arch: "macosx64",
+    },
+    Platform {
+        os: Os::Mac,
+        arch: "aarch64",
+        asset_arch: "aarch64",
+        cef_arch: "macosarm64",
+    },
+];
+
+/// The platform we were built for; building for one missing from
+/// `PLATFORMS` fails here.
+pub const CURRENT: &Platform = match Platform::find(env::consts::OS, env::consts::ARCH) {
+    Some(platform) => platform,
+    None => panic!("unsupported platform, add it to PLATFORMS"),
+};
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+impl Platform {
+    /// By `std::env::consts::OS` and `ARCH` values.
+    pub const fn find(os: &str, arch: &str) -> Option<&'static Self> {
+        let mut i = 0;
+        while i < PLATFORMS.len() {
+            let platform = &PLATFORMS[i];
+            if str_eq(platform.os.name(), os) && str_eq(platform.arch, arch) {
+                return Some(platform);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// `linux_x86_64`
+    fn asset_suffix(&self) -> String {
+        format!("{}_{}", self.os.name(), self.asset_arch)
+    }
+
+    pub fn cef_plugin_loader_path(&self) -> String {
+        format!(
+            "plugins/classicube_cef_loader_{}.{}",
+            self.asset_suffix(),
+            self.os.library_extension()
+        )
+    }
+
+    pub fn cef_plugin_path(&self) -> String {
+        // loaded by path, which has always been spelled from `./` outside
+        // Windows
+        let prefix = match self.os {
+            Os::Windows => "",
+            Os::Linux | Os::Mac => "./",
+        };
+        format!(
+            "{prefix}cef/classicube_cef_{}.{}",
+            self.asset_suffix(),
+            self.os.library_extension()
+        )
+    }
+
+    pub fn cef_exe_path(&self) -> String {
+        format!("cef/cef_{}{}", self.asset_suffix(), self.os.exe_suffix())
+    }
+
+    /// Where releases before the switch to `_` in asset names put the exe.
+    pub fn legacy_cef_exe_path(&self) -> String {
+        format!(
+            "cef/cef-{}-{}{}",
+            self.os.name(),
+            self.asset_arch,
+            self.os.exe_suffix()
+        )
+    }
+
+    pub const fn cef_binary_path(&self) -> &'static str {
+        match self.os {
+            Os::Windows | Os::Linux => "cef/cef_binary",
+            Os::Mac => "cef/Chromium Embedded Framework.framework",
+        }
+    }
+
+    pub const fn cef_binary_path_new(&self) -> &'static str {
+        match self.os {
+            Os::Windows | Os::Linux => "cef/cef_binary-new",
+            Os::Mac => "cef/Chromium Embedded Framework.framework-new",
+        }
+    }
+
+    /// Where the previous binary waits until the new one has loaded.
+    pub const fn cef_binary_path_old(&self) -> &'static str {
+        match self.os {
+            Os::Windows | Os::Linux => "cef/cef_binary-old",
+            Os::Mac => "cef/Chromium Embedded Framework.framework-old",
+        }
+    }
+
+    pub const fn cef_library_name(&self) -> &'static str {
+        match self.os {
+            Os::Windows => "libcef.dll",
+            Os::Linux => "libcef.so",
+            Os::Mac => "Chromium Embedded Framework",
+        }
+    }
+
+    /// Libraries in the CEF binary to load by absolute path ahead of the
+    /// inner plugin, dependencies first, so its imports resolve to them and
+    /// not to whatever the system search finds. Nothing on macOS, where
+    /// loading the framework early hangs the window.
+    pub const fn cef_preload_libraries(&self) -> &'static [&'static str] {
+        match self.os {
+            Os::Windows => &["chrome_elf.dll", "libcef.dll"],
+            Os::Linux => &["libcef.so"],
+            Os::Mac => &[],
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use super::*;
+
+/// The names every platform has always used; downloads and cleanup depend
+/// on them staying exactly the same.
+#[test]
+fn names_match_releases() {
+    let expected = [
+        (
+            "windows",
+            "x86_64",
+            "plugins/classicube_cef_loader_windows_x86_64.dll",
+            "cef/classicube_cef_windows_x86_64.dll",
+            "cef/cef_windows_x86_64.exe",
+            "cef/cef-windows-x86_64.exe",
+            "windows64",
+        ),
+        (
+            "windows",
+            "x86",
+            "plugins/classicube_cef_loader_windows_i686.dll",
+            "cef/classicube_cef_windows_i686.dll",
+            "cef/cef_windows_i686.exe",
+            "cef/cef-windows-i686.exe",
+            "windows32",
+        ),
+        (
+            "linux",
+            "x86_64",
+            "plugins/classicube_cef_loader_linux_x86_64.so",
+            "./cef/classicube_cef_linux_x86_64.so",
+            "cef/cef_linux_x86_64",
+            "cef/cef-linux-x86_64",
+            "linux64",
+        ),
+        (
+            "linux",
+            "x86",
+            "plugins/classicube_cef_loader_linux_i686.so",
+            "./cef/classicube_cef_linux_i686.so",
+            "cef/cef_linux_i686",
+            "cef/cef-linux-i686",
+            "linux32",
+        ),
+        (
+            "linux",
+            "arm",
+            "plugins/classicube_cef_loader_linux_armhf.so",
+            "./cef/classicube_cef_linux_armhf.so",
+            "cef/cef_linux_armhf",
+            "cef/cef-linux-armhf",
+            "linuxarm",
+        ),
+        (
+            "linux",
+            "aarch64",
+            "plugins/classicube_cef_loader_linux_aarch64.so",
+            "./cef/classicube_cef_linux_aarch64.so",
+            "cef/cef_linux_aarch64",
+            "cef/cef-linux-aarch64",
+            "linuxarm64",
+        ),
+        (
+            "macos",
+            "x86_64",
+            "plugins/classicube_cef_loader_macos_x86_64.dylib",
+            "./cef/classicube_cef_macos_x86_64.dylib",
+            "cef/cef_macos_x86_64",
+            "cef/cef-macos-x86_64",
+            "macosx64",
+        ),
+        (
+            "macos",
+            "aarch64",
+            "plugins/classicube_cef_loader_macos_aarch64.dylib",
+            "./cef/classicube_cef_macos_aarch64.dylib",
+            "cef/cef_macos_aarch64",
+            "cef/cef-macos-aarch64",
+            "macosarm64",
+        ),
+    ];
+    assert_eq!(expected.len(), PLATFORMS.len());
+
+    for (os, arch, loader, plugin, exe, legacy_exe, cef_arch) in expected {
+        let platform = Platform::find(os, arch).unwrap();
+        assert_eq!(platform.cef_plugin_loader_path(), loader);
+        assert_eq!(platform.cef_plugin_path(), plugin);
+        assert_eq!(platform.cef_exe_path(), exe);
+        assert_eq!(platform.legacy_cef_exe_path(), legacy_exe);
+        assert_eq!(platform.cef_arch, cef_arch);
+    }
+}
+
+#[test]
+fn binary_paths_per_os() {
+    let linux = Platform::find("linux", "aarch64").unwrap();
+    assert_eq!(linux.cef_binary_path(), "cef/cef_binary");
+    assert_eq!(linux.cef_binary_path_new(), "cef/cef_binary-new");
+    assert_eq!(linux.cef_binary_path_old(), "cef/cef_binary-old");
+    assert_eq!(linux.cef_library_name(), "libcef.so");
+    assert_eq!(linux.cef_preload_libraries(), ["libcef.so"]);
+
+    let windows = Platform::find("windows", "x86").unwrap();
+    assert_eq!(windows.cef_binary_path(), "cef/cef_binary");
+    assert_eq!(windows.cef_library_name(), "libcef.dll");
+    assert_eq!(
+        windows.cef_preload_libraries(),
+        ["chrome_elf.dll", "libcef.dll"]
+    );
+
+    let macos = Platform::find("macos", "aarch64").unwrap();
+    assert_eq!(
+        macos.cef_binary_path(),
+        "cef/Chromium Embedded Framework.framework"
+    );
+    assert_eq!(
+        macos.cef_binary_path_new(),
+        "cef/Chromium Embedded Framework.framework-new"
+    );
+    assert_eq!(
+        macos.cef_binary_path_old(),
+        "cef/Chromium Embedded Framework.framework-old"
+    );
+    assert_eq!(macos.cef_library_name(), "Chromium Embedded Framework");
+    assert!(macos.cef_preload_libraries().is_empty());
+}
+
+#[test]
+fn finds_current() {
+    assert_eq!(CURRENT.os.name(), env::consts::OS);
+    assert_eq!(CURRENT.arch, env::consts::ARCH);
+    assert!(Platform::find("freebsd", "x86_64").is_none());
+    assert!(Platform::find("windows", "aarch64").is_none());
+}
@@ -0,0 +1,108 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use super::events::{self, UpdateEvent};
+
+/// How many release assets / CEF archives may be transferring at once.
+pub const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Minimum time between progress events (`Downloading`, `Extracting`) for a
+/// single component.
+const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+static DOWNLOAD_SLOTS: Semaphore = Semaphore::const_new(MAX_CONCURRENT_DOWNLOADS);
+
+/// A reserved download slot; hold it while sending the request, then turn it
+/// into a `Transfer` once the response (and its `Content-Length`) is known.
+pub struct DownloadSlot {
+    permit: SemaphorePermit<'static>,
+}
+
+pub async fn acquire_slot() -> DownloadSlot {
+    let permit = DOWNLOAD_SLOTS
+        .acquire()
+        .await
+        .expect("DOWNLOAD_SLOTS is never closed");
+    DownloadSlot { permit }
+}
+
+impl DownloadSlot {
+    pub fn start<S: Into<String>>(self, component: S, total: Option<u64>) -> Transfer {
+        let transfer = Transfer {
+            component: component.into(),
+            downloaded: AtomicU64::new(0),
+            total,
+            throttle: Throttle::new(),
+            _permit: self.permit,
+        };
+        transfer.emit();
+        transfer
+    }
+}
+
+/// Lets a progress event through at most once per `EMIT_INTERVAL`; the first
+/// and last one are up to the caller.
+pub struct Throttle {
+    last_emit: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self {
+            last_emit: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        let mut last_emit = self.last_emit.lock().unwrap();
+        if last_emit.elapsed() >= EMIT_INTERVAL {
+            *last_emit = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// An in-flight download, reported as throttled `Downloading` events.
+/// Dropping it emits `Downloaded` and frees its download slot.
+pub struct Transfer {
+    component: String,
+    downloaded: AtomicU64,
+    total: Option<u64>,
+    throttle: Throttle,
+    _permit: SemaphorePermit<'static>,
+}
+
+impl Transfer {
+    pub fn add(&self, bytes: usize) {
+        self.downloaded.fetch_add(bytes as u64, Ordering::SeqCst);
+        if self.throttle.due() {
+            self.emit();
+        }
+    }
+
+    fn emit(&self) {
+        events::emit(UpdateEvent::Downloading {
+            component: self.component.clone(),
+            bytes: self.downloaded.load(Ordering::SeqCst),
+            total: self.total,
+        });
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        self.emit();
+        events::emit(UpdateEvent::Downloaded {
+            component: self.component.clone(),
+        });
+    }
+}
@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::async_manager;
+
+/// What the last update replaced, kept until the inner plugin has loaded with
+/// the new files.
+pub const JOURNAL_PATH: &str = "cef/update_journal.json";
+
+/// Tag of a CEF Plugin release that didn't load and was rolled back, so it
+/// isn't installed again. Delete it to give the release another try.
+pub const REJECTED_PATH: &str = "cef/update_rejected.txt";
+
+/// The plugin and CEF binary updates run concurrently and both record here.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Everything an update replaced, so it can be put back if the new version
+/// doesn't load.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    /// Installed path to where its previous version was moved (`-old`).
+    #[serde(default)]
+    pub replaced: BTreeMap<PathBuf, PathBuf>,
+
+    /// Marker files as they were before the update, `None` if missing.
+    #[serde(default)]
+    pub markers: BTreeMap<PathBuf, Option<String>>,
+
+    /// The CEF Plugin release being installed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+
+    /// Set once the new version starts loading; still set at the next start
+    /// means loading it took the game down.
+    #[serde(default)]
+    pub loading: bool,
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    let result = match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+impl Journal {
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("read {path:?}")),
+        };
+        let journal = serde_json::from_str(&text).with_context(|| format!("parse {path:?}"))?;
+        Ok(Some(journal))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("write {path:?}"))
+    }
+
+    /// Remember `paths` as they are now, unless already recorded.
+    pub fn record_markers<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<()> {
+        for path in paths {
+            let path = path.as_ref();
+            if self.markers.contains_key(path) {
+                continue;
+            }
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => Some(contents),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).with_context(|| format!("read {path:?}")),
+            };
+            self.markers.insert(path.to_path_buf(), contents);
+        }
+        Ok(())
+    }
+
+    /// Drop the previous versions, the new ones work.
+    pub fn commit(&self) -> Result<()> {
+        for old_path in self.replaced.values() {
+            debug!("removing {:?}", old_path);
+            remove_path(old_path).with_context(|| format!("remove {old_path:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Move the previous versions back and restore the markers. Paths whose
+    /// previous version is gone are left as they are.
+    pub fn revert(&self) -> Result<()> {
+        for (path, old_path) in &self.replaced {
+            if old_path.symlink_metadata().is_err() {
+                warn!("{:?} is gone, keeping {:?}", old_path, path);
+                continue;
+            }
+            debug!("restoring {:?} from {:?}", path, old_path);
+            remove_path(path).with_context(|| format!("remove {path:?}"))?;
+            fs::rename(old_path, path)
+                .with_context(|| format!("rename {old_path:?} -> {path:?}"))?;
+        }
+
+        for (path, contents) in &self.markers {
+            match contents {
+                Some(contents) => {
+                    fs::write(path, contents).with_context(|| format!("write {path:?}"))?
+                }
+                None => remove_path(path).with_context(|| format!("remove {path:?}"))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Change the journal, starting one if there's none.
+fn update(f: impl FnOnce(&mut Journal) -> Result<()>) -> Result<()> {
+    let _lock = LOCK.lock().unwrap();
+    let path = Path::new(JOURNAL_PATH);
+    let mut journal = Journal::load(path)?.unwrap_or_default();
+    f(&mut journal)?;
+    journal.save(path)
+}
+
+/// Call before an update overwrites `paths`.
+pub fn record_markers<P: AsRef<Path>>(paths: &[P]) -> Result<()> {
+    update(|journal| journal.record_markers(paths))
+}
+
+/// Call before moving the installed `path` aside to `old_path`.
+pub fn record_replaced(path: &Path, old_path: &Path) -> Result<()> {
+    update(|journal| {
+        journal
+            .replaced
+            .entry(path.to_path_buf())
+            .or_insert_with(|| old_path.to_path_buf());
+        Ok(())
+    })
+}
+
+pub fn record_release(release: &str) -> Result<()> {
+    update(|journal| {
+        journal.release = Some(release.to_string());
+        Ok(())
+    })
+}
+
+/// The journal of an update that hasn't been loaded yet.
+pub fn pending() -> Result<Option<Journal>> {
+    let _lock = LOCK.lock().unwrap();
+    Journal::load(Path::new(JOURNAL_PATH))
+}
+
+/// File names `cleanup` has to leave alone: the previous versions an
+/// unverified update can still go back to.
+pub fn kept_file_names() -> Result<Vec<OsString>> {
+    Ok(pending()?
+        .map(|journal| {
+            journal
+                .replaced
+                .values()
+                .filter_map(|old_path| old_path.file_name().map(OsString::from))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Mark the pending update as being loaded, right before loading it.
+pub fn loading() -> Result<()> {
+    if pending()?.is_none() {
+        return Ok(());
+    }
+    update(|journal| {
+        journal.loading = true;
+        Ok(())
+    })
+}
+
+/// The update loaded fine. The journal goes first, so nothing rolls it back
+/// any more, then the space its previous version took is freed in the
+/// background; whatever can't be removed now (e.g. locked by a virus scanner)
+/// is a `-old` leftover for `cleanup`.
+pub fn commit() -> Result<()> {
+    let journal = {
+        let _lock = LOCK.lock().unwrap();
+        let path = Path::new(JOURNAL_PATH);
+        let Some(journal) = Journal::load(path)? else {
+            return Ok(());
+        };
+        fs::remove_file(path).with_context(|| format!("remove {path:?}"))?;
+        journal
+    };
+
+    async_manager::spawn(async move {
+        match tokio::task::spawn_blocking(move || journal.commit()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("couldn't remove the previous version: {:#}", e),
+            Err(e) => warn!("couldn't remove the previous version: {}", e),
+        }
+    });
+
+    Ok(())
+}
+
+/// Put back what the pending update replaced and don't install its release
+/// again. Returns `false` if there was nothing to go back to.
+pub fn revert() -> Result<bool> {
+    let _lock = LOCK.lock().unwrap();
+    let path = Path::new(JOURNAL_PATH);
+    let Some(journal) = Journal::load(path)? else {
+        return Ok(false);
+    };
+    journal.revert()?;
+    if let Some(release) = &journal.release {
+        fs::write(REJECTED_PATH, release).with_context(|| format!("write {REJECTED_PATH}"))?;
+    }
+    fs::remove_file(path).with_context(|| format!("remove {path:?}"))?;
+    Ok(!journal.replaced.is_empty())
+}
+
+/// At startup: if the last session died while loading an update, go back to
+/// what worked before. Returns whether anything was reverted.
+pub fn recover() -> Result<bool> {
+    match pending()? {
+        Some(journal) if journal.loading => revert(),
+        _ => Ok(false),
+    }
+}
+
+/// See `REJECTED_PATH`.
+pub fn rejected() -> Option<String> {
+    fs::read_to_string(REJECTED_PATH)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
@@ -0,0 +1,100 @@
+use super::*;
+use crate::test_util::scratch_dir;
+
+/// An installed CEF binary, plugin and their markers, then an update that
+/// moves the old ones aside the way `cef_binary` and `github_release` do.
+fn update(dir: &Path) -> Journal {
+    let binary = dir.join("cef_binary");
+    let plugin = dir.join("classicube_cef_linux_x86_64.so");
+    let version = dir.join("cef_binary.txt");
+    let manifest = dir.join("cef_binary_manifest.json");
+    fs::create_dir_all(&binary).unwrap();
+    fs::write(binary.join("libcef.so"), b"old libcef").unwrap();
+    fs::write(&plugin, b"old plugin").unwrap();
+    fs::write(&version, b"1.0.0").unwrap();
+
+    let mut journal = Journal::default();
+    journal.record_markers(&[&version, &manifest]).unwrap();
+    for path in [&binary, &plugin] {
+        let mut old_name = path.file_name().unwrap().to_os_string();
+        old_name.push("-old");
+        let old_path = path.with_file_name(old_name);
+        journal.replaced.insert(path.clone(), old_path.clone());
+        fs::rename(path, &old_path).unwrap();
+    }
+    journal.release = Some("v2.0.0".to_string());
+
+    fs::create_dir_all(&binary).unwrap();
+    fs::write(binary.join("libcef.so"), b"new libcef").unwrap();
+    fs::write(&plugin, b"new plugin").unwrap();
+    fs::write(&version, b"2.0.0").unwrap();
+    fs::write(&manifest, b"{}").unwrap();
+
+    // a second capture doesn't overwrite what was there before the update
+    journal.record_markers(&[&version]).unwrap();
+
+    let path = dir.join("update_journal.json");
+    journal.save(&path).unwrap();
+    let loaded = Journal::load(&path).unwrap().unwrap();
+    assert_eq!(loaded, journal);
+    loaded
+}
+
+#[test]
+fn revert_restores_previous_version() {
+    let dir = scratch_dir("revert");
+    let journal = update(&dir);
+
+    journal.revert().unwrap();
+
+    assert_eq!(
+        fs::read(dir.join("cef_binary").join("libcef.so")).unwrap(),
+        b"old libcef"
+    );
+    assert_eq!(
+        fs::read(dir.join("classicube_cef_linux_x86_64.so")).unwrap(),
+        b"old plugin"
+    );
+    assert_eq!(fs::read(dir.join("cef_binary.txt")).unwrap(), b"1.0.0");
+    // didn't exist before the update
+    assert!(!dir.join("cef_binary_manifest.json").exists());
+    assert!(!dir.join("cef_binary-old").exists());
+    assert!(!dir.join("classicube_cef_linux_x86_64.so-old").exists());
+}
+
+#[test]
+fn commit_removes_previous_version() {
+    let dir = scratch_dir("commit");
+    let journal = update(&dir);
+
+    journal.commit().unwrap();
+
+    assert!(!dir.join("cef_binary-old").exists());
+    assert!(!dir.join("classicube_cef_linux_x86_64.so-old").exists());
+    assert_eq!(
+        fs::read(dir.join("cef_binary").join("libcef.so")).unwrap(),
+        b"new libcef"
+    );
+    assert_eq!(fs::read(dir.join("cef_binary.txt")).unwrap(), b"2.0.0");
+
+    // nothing left to remove the second time
+    journal.commit().unwrap();
+}
+
+#[test]
+fn revert_keeps_files_without_a_previous_version() {
+    let dir = scratch_dir("revert-missing");
+    let journal = update(&dir);
+    fs::remove_dir_all(dir.join("cef_binary-old")).unwrap();
+
+    journal.revert().unwrap();
+
+    assert_eq!(
+        fs::read(dir.join("cef_binary").join("libcef.so")).unwrap(),
+        b"new libcef"
+    );
+    assert_eq!(
+        fs::read(dir.join("classicube_cef_linux_x86_64.so")).unwrap(),
+        b"old plugin"
+    );
+}
@@ -0,0 +1,444 @@
+#[cfg(test)]
+mod tests;
+
+use std::time::{Duration, Instant};
+
+use classicube_helpers::{async_manager, chat::print, color};
+use tokio::sync::mpsc;
+
+use super::{
+    events::{self, UpdateEvent},
+    human_bytes,
+};
+use crate::{announce, status};
+
+/// How often progress events may redraw the client status line.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How quickly the shown download rate follows changes; a few seconds keeps
+/// the ETA from jumping around with every burst.
+const RATE_SMOOTHING: Duration = Duration::from_secs(3);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UiAction {
+    Print(String),
+    /// Big centered `title`, with the details in chat.
+    Announce {
+        title: String,
+        message: String,
+    },
+}
+
+/// What a component is doing besides downloading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Phase {
+    Decompressing,
+    Extracting { files: usize },
+    Stripping { file: String },
+    Finalizing,
+    Verifying,
+}
+
+impl Phase {
+    fn describe(&self) -> String {
+        match self {
+            Self::Decompressing => "decompressing".to_string(),
+            Self::Extracting { files } => format!("extracting {files} files"),
+            Self::Stripping { file } => format!("stripping {file}"),
+            Self::Finalizing => "finalizing".to_string(),
+            Self::Verifying => "verifying".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Download {
+    bytes: u64,
+    total: Option<u64>,
+    sampled_at: Instant,
+    sampled_bytes: u64,
+    /// Smoothed bytes per second, once there are two samples.
+    rate: Option<f64>,
+}
+
+impl Download {
+    fn new(bytes: u64, total: Option<u64>, now: Instant) -> Self {
+        Self {
+            bytes,
+            total,
+            sampled_at: now,
+            sampled_bytes: bytes,
+            rate: None,
+        }
+    }
+
+    fn update(&mut self, bytes: u64, total: Option<u64>, now: Instant) {
+        self.bytes = bytes;
+        self.total = total;
+
+        let elapsed = now.saturating_duration_since(self.sampled_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+        let current = bytes.saturating_sub(self.sampled_bytes) as f64 / elapsed;
+        let weight = 1.0 - (-elapsed / RATE_SMOOTHING.as_secs_f64()).exp();
+        self.rate = Some(match self.rate {
+            Some(rate) => rate + (current - rate) * weight,
+            None => current,
+        });
+        self.sampled_at = now;
+        self.sampled_bytes = bytes;
+    }
+}
+
+#[derive(Debug)]
+struct Active {
+    component: String,
+    download: Option<Download>,
+    phase: Option<Phase>,
+}
+
+/// Chat/status line view of the update event stream. Kept free of any
+/// ClassiCube calls so it can be driven by tests.
+#[derive(Debug, Default)]
+pub struct Ui {
+    active: Vec<Active>,
+}
+
+fn human_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{seconds}s")
+    } else if seconds < 60 * 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h {:02}m", seconds / (60 * 60), seconds / 60 % 60)
+    }
+}
+
+impl Ui {
+    fn entry(&mut self, component: String) -> &mut Active {
+        let index = match self.active.iter().position(|a| a.component == component) {
+            Some(index) => index,
+            None => {
+                self.active.push(Active {
+                    component,
+                    download: None,
+                    phase: None,
+                });
+                self.active.len() - 1
+            }
+        };
+        &mut self.active[index]
+    }
+
+    fn set_phase(&mut self, component: String, phase: Phase) {
+        self.entry(component).phase = Some(phase);
+    }
+
+    fn remove(&mut self, component: &str) {
+        self.active.retain(|a| a.component != component);
+    }
+
+    pub fn apply(&mut self, event: UpdateEvent) -> Option<UiAction> {
+        self.apply_at(event, Instant::now())
+    }
+
+    /// `apply`, with the time the event arrived for working out rates.
+    pub fn apply_at(&mut self, event: UpdateEvent, now: Instant) -> Option<UiAction> {
+        match event {
+            UpdateEvent::Checking { .. } => None,
+
+            UpdateEvent::UpToDate { component } => {
+                // ends a `Verifying` that found nothing wrong
+                self.remove(&component);
+                None
+            }
+
+            UpdateEvent::Updating { component, version } => Some(UiAction::Print(format!(
+                "{}Updating {}{} {}to {}{}",
+                color::PINK,
+                color::LIME,
+                component,
+                color::PINK,
+                color::GREEN,
+                version
+            ))),
+
+            UpdateEvent::Downloading {
+                component,
+                bytes,
+                total,
+            } => {
+                let entry = self.entry(component);
+                match &mut entry.download {
+                    Some(download) => download.update(bytes, total, now),
+                    None => entry.download = Some(Download::new(bytes, total, now)),
+                }
+                None
+            }
+
+            UpdateEvent::Downloaded { component } => {
+                if let Some(entry) = self.active.iter_mut().find(|a| a.component == component) {
+                    entry.download = None;
+                    if entry.phase.is_none() {
+                        self.remove(&component);
+                    }
+                }
+                None
+            }
+
+            UpdateEvent::Decompressing { component } => {
+                self.set_phase(component, Phase::Decompressing);
+                None
+            }
+
+            UpdateEvent::Extracting { component, files } => {
+                self.set_phase(component, Phase::Extracting { files });
+                None
+            }
+
+            UpdateEvent::Stripping { component, file } => {
+                self.set_phase(component, Phase::Stripping { file });
+                None
+            }
+
+            UpdateEvent::Finalizing { component } => {
+                self.set_phase(component, Phase::Finalizing);
+                None
+            }
+
+            UpdateEvent::Verifying { component } => {
+                self.set_phase(component, Phase::Verifying);
+                None
+            }
+
+            UpdateEvent::Installed { component, version } => {
+                self.remove(&component);
+                Some(UiAction::Print(format!(
+                    "{}Updated to {}{} {}",
+                    color::GOLD,
+                    color::GREEN,
+                    component,
+                    version
+                )))
+            }
+
+            UpdateEvent::Repairing { component, files } => {
+                self.remove(&component);
+                Some(UiAction::Print(format!(
+                    "{}Repairing {}{} {}({}{}{} missing or damaged files)",
+                    color::PINK,
+                    color::LIME,
+                    component,
+                    color::PINK,
+                    color::LIME,
+                    files,
+                    color::PINK,
+                )))
+            }
+
+            UpdateEvent::Repaired { component, files } => {
+                self.remove(&component);
+                Some(UiAction::Print(format!(
+                    "{}Repaired {}{} {}({}{}{} files)",
+                    color::GOLD,
+                    color::GREEN,
+                    component,
+                    color::GOLD,
+                    color::GREEN,
+                    files,
+                    color::GOLD,
+                )))
+            }
+
+            UpdateEvent::Skipped { component, reason } => {
+                self.remove(&component);
+                Some(UiAction::Print(format!(
+                    "{}Skipping {} update: {}{}",
+                    color::RED,
+                    component,
+                    color::WHITE,
+                    reason
+                )))
+            }
+
+            UpdateEvent::Failed { component, error } => {
+                // a failure aborts the whole update run
+                self.active.clear();
+                Some(UiAction::Print(format!(
+                    "{}Failed to update {}: {}{}",
+                    color::RED,
+                    component,
+                    color::WHITE,
+                    error
+                )))
+            }
+
+            UpdateEvent::CleanedUp { files, bytes } => Some(UiAction::Print(format!(
+                "{}Cleaned up {}{}{} old update files ({}{}{})",
+                color::PINK,
+                color::LIME,
+                files,
+                color::PINK,
+                color::LIME,
+                human_bytes(bytes),
+                color::PINK,
+            ))),
+
+            UpdateEvent::RestartRequired { component, version } => Some(UiAction::Announce {
+                title: format!(
+                    "{}{} updated, please restart ClassiCube",
+                    color::GOLD,
+                    component
+                ),
+                message: format!(
+                    "{}{} {}{}{} was installed. {}Restart ClassiCube{} to finish updating.",
+                    color::GOLD,
+                    component,
+                    color::GREEN,
+                    version,
+                    color::GOLD,
+                    color::YELLOW,
+                    color::GOLD,
+                ),
+            }),
+        }
+    }
+
+    /// Text for the client status line, empty when nothing is in progress.
+    pub fn status_message(&self) -> String {
+        let downloads: Vec<_> = self
+            .active
+            .iter()
+            .filter_map(|a| a.download.as_ref().map(|download| (a, download)))
+            .collect();
+
+        if !downloads.is_empty() {
+            let bytes: u64 = downloads.iter().map(|(_, d)| d.bytes).sum();
+            let total: Option<u64> = downloads.iter().map(|(_, d)| d.total).sum();
+            let rate: Option<f64> = downloads.iter().map(|(_, d)| d.rate).sum();
+
+            let what = if let [(active, _)] = downloads.as_slice() {
+                active.component.clone()
+            } else {
+                format!("{} files", downloads.len())
+            };
+
+            let mut message = format!(
+                "{}Downloading {} ({}{}",
+                color::PINK,
+                what,
+                color::LIME,
+                human_bytes(bytes),
+            );
+            if let Some(total) = total.filter(|total| *total > 0) {
+                message.push_str(&format!(
+                    "{} of {}{}{}, {}{:.2}%",
+                    color::PINK,
+                    color::LIME,
+                    human_bytes(total),
+                    color::PINK,
+                    color::LIME,
+                    (bytes as f32 / total as f32) * 100.0
+                ));
+            }
+            message.push_str(&format!("{})", color::PINK));
+
+            if let Some(rate) = rate.filter(|rate| *rate >= 1.0) {
+                message.push_str(&format!(
+                    " at {}{}/s{}",
+                    color::LIME,
+                    human_bytes(rate as u64),
+                    color::PINK
+                ));
+                if let Some(total) = total {
+                    let left = total.saturating_sub(bytes) as f64 / rate;
+                    message.push_str(&format!(
+                        ", {}{}{} left",
+                        color::LIME,
+                        human_duration(Duration::from_secs_f64(left)),
+                        color::PINK
+                    ));
+                }
+            }
+
+            // the archive unpacks as it arrives; say so when that's the hold up
+            if let [(active, _)] = downloads.as_slice()
+                && let Some(phase) = &active.phase
+            {
+                message.push_str(&format!(", {}", phase.describe()));
+            }
+
+            return message;
+        }
+
+        let Some(Active {
+            component,
+            phase: Some(phase),
+            ..
+        }) = self.active.first()
+        else {
+            return String::new();
+        };
+        match phase {
+            Phase::Extracting { files } => format!(
+                "{}Extracting {} ({}{} files{})",
+                color::PINK,
+                component,
+                color::LIME,
+                files,
+                color::PINK,
+            ),
+            Phase::Stripping { file } => format!(
+                "{}Stripping {}{}{} from {}",
+                color::PINK,
+                color::LIME,
+                file,
+                color::PINK,
+                component
+            ),
+            Phase::Decompressing => format!("{}Decompressing {}", color::PINK, component),
+            Phase::Finalizing => format!("{}Finalizing {}", color::PINK, component),
+            Phase::Verifying => format!("{}Verifying {}", color::PINK, component),
+        }
+    }
+}
+
+/// Start the chat/status line subscriber on the main thread.
+pub fn start() {
+    // subscribe now so nothing emitted before the main thread picks us up is lost
+    let rx = events::subscribe();
+    async_manager::spawn_on_main_thread(run(rx));
+}
+
+async fn run(mut rx: mpsc::UnboundedReceiver<UpdateEvent>) {
+    let mut ui = Ui::default();
+    let mut shown = String::new();
+    let mut last_drawn: Option<Instant> = None;
+
+    while let Some(event) = rx.recv().await {
+        let is_progress = matches!(
+            event,
+            UpdateEvent::Downloading { .. } | UpdateEvent::Extracting { .. }
+        );
+
+        match ui.apply(event) {
+            Some(UiAction::Print(message)) => print(message),
+            Some(UiAction::Announce { title, message }) => {
+                announce(title);
+                print(message);
+            }
+            None => {}
+        }
+
+        let message = ui.status_message();
+        let throttled =
+            is_progress && last_drawn.is_some_and(|drawn| drawn.elapsed() < STATUS_INTERVAL);
+        if message != shown && !throttled {
+            status(message.clone());
+            shown = message;
+            last_drawn = Some(Instant::now());
+        }
+    }
+}
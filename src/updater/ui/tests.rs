@@ -0,0 +1,185 @@
+use super::*;
+
+fn downloading(component: &str, bytes: u64, total: Option<u64>) -> UpdateEvent {
+    UpdateEvent::Downloading {
+        component: component.to_string(),
+        bytes,
+        total,
+    }
+}
+
+#[test]
+fn aggregates_concurrent_downloads() {
+    let mut ui = Ui::default();
+    assert_eq!(ui.status_message(), "");
+
+    ui.apply(downloading("a.so", 50, Some(100)));
+    assert!(ui.status_message().contains("a.so"));
+    assert!(ui.status_message().contains("50.00%"));
+
+    ui.apply(downloading("CEF Binary", 50, Some(300)));
+    assert!(ui.status_message().contains("2 files"));
+    assert!(ui.status_message().contains("25.00%"));
+
+    ui.apply(UpdateEvent::Downloaded {
+        component: "a.so".to_string(),
+    });
+    assert!(ui.status_message().contains("CEF Binary"));
+}
+
+#[test]
+fn extraction_shows_after_download_finishes() {
+    let mut ui = Ui::default();
+    let extracting = UpdateEvent::Extracting {
+        component: "CEF Binary".to_string(),
+        files: 12,
+    };
+
+    ui.apply(downloading("CEF Binary", 10, None));
+    ui.apply(extracting.clone());
+    assert!(ui.status_message().contains("Downloading"));
+
+    ui.apply(UpdateEvent::Downloaded {
+        component: "CEF Binary".to_string(),
+    });
+    ui.apply(extracting);
+    assert!(ui.status_message().contains("Extracting"));
+    assert!(ui.status_message().contains("12"));
+
+    let action = ui.apply(UpdateEvent::Installed {
+        component: "CEF Binary".to_string(),
+        version: "134.3.8".to_string(),
+    });
+    assert!(matches!(action, Some(UiAction::Print(message)) if message.contains("134.3.8")));
+    assert_eq!(ui.status_message(), "");
+}
+
+#[test]
+fn failure_clears_status_line() {
+    let mut ui = Ui::default();
+    ui.apply(downloading("a.so", 1, None));
+
+    let action = ui.apply(UpdateEvent::Failed {
+        component: "CEF".to_string(),
+        error: "rate limited".to_string(),
+    });
+    assert!(matches!(action, Some(UiAction::Print(message)) if message.contains("rate limited")));
+    assert_eq!(ui.status_message(), "");
+}
+
+#[test]
+fn restart_is_announced() {
+    let mut ui = Ui::default();
+    let action = ui.apply(UpdateEvent::RestartRequired {
+        component: "CEF Loader".to_string(),
+        version: "v2.2.0".to_string(),
+    });
+    assert!(
+        matches!(action, Some(UiAction::Announce { message, .. }) if message.contains("v2.2.0"))
+    );
+}
+
+#[test]
+fn repair_ends_verification() {
+    let mut ui = Ui::default();
+    let verifying = UpdateEvent::Verifying {
+        component: "CEF Binary".to_string(),
+    };
+
+    ui.apply(verifying.clone());
+    assert!(ui.status_message().contains("Verifying"));
+    ui.apply(UpdateEvent::UpToDate {
+        component: "CEF Binary".to_string(),
+    });
+    assert_eq!(ui.status_message(), "");
+
+    ui.apply(verifying);
+    let action = ui.apply(UpdateEvent::Repairing {
+        component: "CEF Binary".to_string(),
+        files: 2,
+    });
+    assert!(matches!(action, Some(UiAction::Print(message)) if message.contains("Repairing")));
+    assert_eq!(ui.status_message(), "");
+
+    ui.apply(UpdateEvent::Extracting {
+        component: "CEF Binary".to_string(),
+        files: 40,
+    });
+    ui.apply(UpdateEvent::Repaired {
+        component: "CEF Binary".to_string(),
+        files: 2,
+    });
+    assert_eq!(ui.status_message(), "");
+}
+
+#[test]
+fn shows_rate_and_time_left() {
+    let mut ui = Ui::default();
+    let start = Instant::now();
+
+    ui.apply_at(downloading("CEF Binary", 0, Some(10 * 1024 * 1024)), start);
+    assert!(!ui.status_message().contains("/s"));
+
+    ui.apply_at(
+        downloading("CEF Binary", 1024 * 1024, Some(10 * 1024 * 1024)),
+        start + Duration::from_secs(1),
+    );
+    let message = ui.status_message();
+    assert!(message.contains("1.0 MB"), "{message}");
+    assert!(message.contains("10.0 MB"), "{message}");
+    assert!(message.contains("1.0 MB/s"), "{message}");
+    assert!(message.contains("9s"), "{message}");
+    assert!(message.contains("left"), "{message}");
+
+    // a stall pulls the rate down gradually rather than to zero
+    ui.apply_at(
+        downloading("CEF Binary", 1024 * 1024, Some(10 * 1024 * 1024)),
+        start + Duration::from_secs(2),
+    );
+    let message = ui.status_message();
+    assert!(message.contains("KB/s"), "{message}");
+}
+
+#[test]
+fn shows_what_the_extractor_is_doing() {
+    let mut ui = Ui::default();
+
+    ui.apply(downloading("CEF Binary", 10, None));
+    ui.apply(UpdateEvent::Stripping {
+        component: "CEF Binary".to_string(),
+        file: "libcef.so".to_string(),
+    });
+    let message = ui.status_message();
+    assert!(message.contains("Downloading"), "{message}");
+    assert!(message.contains("stripping libcef.so"), "{message}");
+
+    ui.apply(UpdateEvent::Downloaded {
+        component: "CEF Binary".to_string(),
+    });
+    assert!(ui.status_message().contains("Stripping"));
+
+    ui.apply(UpdateEvent::Finalizing {
+        component: "CEF Binary".to_string(),
+    });
+    assert!(ui.status_message().contains("Finalizing CEF Binary"));
+
+    // unpacking a cached archive never downloads
+    ui.apply(UpdateEvent::Decompressing {
+        component: "CEF Debug Symbols".to_string(),
+    });
+    ui.remove("CEF Binary");
+    assert!(
+        ui.status_message()
+            .contains("Decompressing CEF Debug Symbols")
+    );
+}
+
+#[test]
+fn formats_durations() {
+    assert_eq!(human_duration(Duration::from_secs(9)), "9s");
+    assert_eq!(human_duration(Duration::from_secs(125)), "2m 05s");
+    assert_eq!(
+        human_duration(Duration::from_secs(3 * 3600 + 120)),
+        "3h 02m"
+    );
+}